@@ -0,0 +1,19 @@
+#pragma once
+#include "rust/cxx.h"
+
+struct CppToken;
+
+// Base class of the tokenizers implemented in C++. Subclass it and register an
+// instance with register_cpp_tokenizer(), FieldMapping.tokenizer and
+// FieldMapping.search_tokenizer can then refer to it by name.
+//
+// tokenize() is called concurrently by the indexing threads and the query
+// parser, so implementations must be thread safe.
+class CppTokenizer {
+public:
+  virtual ~CppTokenizer() = default;
+
+  // Splits the UTF-8 text into tokens, the offsets of a CppToken are byte
+  // offsets into text.
+  virtual rust::Vec<CppToken> tokenize(rust::Str text) const = 0;
+};
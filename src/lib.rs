@@ -10,36 +10,70 @@ use log::LevelFilter;
 use std::ops::Bound;
 use std::time::Instant;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::Arc;
 
 use roaring::RoaringTreemap;
 
 use tantivy::IndexReader;
-use tantivy::collector::{TopDocs, DocSetCollector};
+use tantivy::collector::{Collector, Count, SegmentCollector, TopDocs, DocSetCollector};
 use tantivy::query::QueryParser;
+use tantivy::SnippetGenerator;
+use tantivy::tokenizer::{AsciiFoldingFilter, Language, LowerCaser, NgramTokenizer, RawTokenizer, RemoveLongFilter, SimpleTokenizer, Stemmer, StopWordFilter, TextAnalyzer};
+use tantivy::tokenizer::{BoxTokenStream, Token, TokenStream, Tokenizer};
+
+use cxx::SharedPtr;
 use tantivy::schema::*;
 use tantivy::fastfield::Column;
 use tantivy::Index;
+use tantivy::IndexSettings;
+use tantivy::DateTime;
+use tantivy::{DocAddress, Score, SegmentReader};
 use tantivy::ReloadPolicy;
 use tantivy::IndexWriter;
+use tantivy::UserOperation;
+use tantivy::indexer::JsonTermWriter;
 use tantivy::directory::MmapDirectory;
 use tantivy::merge_policy::LogMergePolicy;
-use tantivy::query::{BooleanQuery, Occur, RangeQuery, Query, TermQuery};
+use tantivy::query::{AllQuery, BooleanQuery, BoostQuery, ConstScoreQuery, DisjunctionMaxQuery, FuzzyTermQuery, MoreLikeThisQuery, MoreLikeThisQueryBuilder, Occur, PhraseQuery, RangeQuery, Query, RegexQuery, TermQuery, TermSetQuery};
 
 // include shared struct in Rust
 use crate::ffi::DocumentField;
 use crate::ffi::FieldType;
 use crate::ffi::IdDocument;
 use crate::ffi::FieldMapping;
+use crate::ffi::FieldOption;
+use crate::ffi::RecordOption;
+use crate::ffi::TDatePrecision;
 use crate::ffi::TOccur;
 use crate::ffi::SearchParam;
+use crate::ffi::SearchAfter;
+use crate::ffi::SortField;
+use crate::ffi::SortOrder;
+use crate::ffi::SnippetFormat;
+use crate::ffi::HighlightRange;
+use crate::ffi::Snippet;
+use crate::ffi::SnippetParam;
+use crate::ffi::MoreLikeThisParam;
 use crate::ffi::IndexParam;
+use crate::ffi::AnalyzerConfig;
+use crate::ffi::SynonymGroup;
+use crate::ffi::BaseTokenizer;
+use crate::ffi::CppTokenizer;
+use crate::ffi::SchemaDiff;
+use crate::ffi::SchemaDiffKind;
 use crate::ffi::RangeBound;
 use crate::ffi::StringBound;
 use crate::ffi::FloatBound;
 use crate::ffi::LongBound;
+use crate::ffi::ULongBound;
+use crate::ffi::BytesBound;
 
 use std::error::Error;
 
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+
 #[cxx::bridge]
 mod ffi {
     
@@ -53,11 +87,51 @@ mod ffi {
         str_field = 5, // untokenized and indexed
         bool_field = 6,
         text_field = 7, // tokenized and indexed
+        date_field = 8, // RFC 3339 string or epoch millis
+        ulong_field = 9,
+        bytes_field = 10, // base64 encoded in DocumentField.field_value
+        json_field = 11, // JSON object string, queried by dotted path such as attrs.color
+    }
+
+    /// Precision of the fast values of a date_field, fast values are truncated to it.
+    /// The indexed terms are always truncated to seconds, whatever the precision.
+    #[derive(Debug, Clone, Copy)]
+    enum TDatePrecision {
+        unspecified_precision = 0, // seconds
+        seconds = 1,
+        milliseconds = 2,
+        microseconds = 3,
+    }
+
+    /// Per-field index flag, unspecified keeps the default of the FieldType.
+    #[derive(Debug, Clone, Copy)]
+    enum FieldOption {
+        unspecified_option = 0,
+        enabled = 1,
+        disabled = 2,
+    }
+
+    /// What the inverted index records for an indexed str_field/text_field.
+    #[derive(Debug, Clone, Copy)]
+    enum RecordOption {
+        unspecified_record_option = 0,
+        basic = 1, // doc ids only
+        freqs = 2, // doc ids and term frequencies
+        positions = 3, // doc ids, term frequencies and positions, required by phrase queries
     }
 
     struct FieldMapping{
         field_name: String,
         field_type: FieldType,
+        stored: FieldOption,
+        indexed: FieldOption,
+        fast: FieldOption, // numeric and bool fields only
+        fieldnorms: FieldOption,
+        record_option: RecordOption,
+        tokenizer: String, // str_field and text_field only, empty for the default tokenizer of the FieldType
+        search_tokenizer: String, // analyzer of the query text in search(), empty to use the indexing tokenizer
+        date_precision: TDatePrecision, // date_field only
+        multi_valued: bool, // fast fields use a multi value column and accept several values per document, other fields always accept several values
     }
 
     // Shared structs with fields visible to both languages.
@@ -68,12 +142,127 @@ mod ffi {
         field_type: FieldType, // "String", "Long", "Int", "Double", "Boolean"
     }
 
+    /// Cursor for "search after" pagination, built from the score and docId of the last hit of the previous page.
+    /// Pages after a cursor break score ties by docId asc, a disabled cursor keeps the faster ranking of TopDocs
+    /// which breaks ties by segment order. To rank the first page like the next ones, enable the cursor with
+    /// score = +infinity and docId = i64::MIN, this costs the pruning of TopDocs.
+    struct SearchAfter{
+        enabled: bool,
+        score: f32,
+        docId: i64,
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    enum SortOrder {
+        Asc,
+        Desc,
+    }
+
+    /// Fast field to rank the hits on, the field must be a FAST numeric or bool field.
+    struct SortField{
+        field_name: String,
+        order: SortOrder,
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    enum SchemaDiffKind {
+        missing_in_index, // the field mapping has no field in the stored schema
+        missing_in_mappings, // the stored schema has a field without field mapping
+        options_mismatch, // same field name, different type or options
+    }
+
+    /// Difference between a field mapping and the schema stored in an existing index,
+    /// expected and actual are the JSON field entries of tantivy, empty if the field is missing.
+    struct SchemaDiff{
+        field_name: String,
+        kind: SchemaDiffKind,
+        expected: String,
+        actual: String,
+    }
+
     struct SearchParam{
         topK: usize,
+        offset: usize, // number of hits to skip, applied after search_after
+        search_after: SearchAfter, // only hits ranked after the cursor are returned, ranked by score desc then docId asc
+        sort_by: Vec<SortField>, // rank by these fast fields instead of score, later fields break ties, then docId asc
+        with_field_values: bool, // fill IdDocument.fieldValues with the stored fields of each hit
+        return_fields: Vec<String>, // stored fields to return, empty for all stored fields
+        snippet: SnippetParam, // highlighted fragments of text fields returned in IdDocument.snippets
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    enum SnippetFormat {
+        unspecified_snippet_format = 0, // html
+        html, // Snippet.html with the matched terms between pre_tag and post_tag
+        ranges, // Snippet.fragment and Snippet.highlights only
+    }
+
+    struct SnippetParam{
+        fields: Vec<String>, // stored text fields to generate snippets for, empty for no snippets
+        max_num_chars: usize, // max length of a fragment, 0 for 150
+        format: SnippetFormat,
+        pre_tag: String, // empty for <b>
+        post_tag: String, // empty for </b>
+    }
+
+    /// Tuning of more_like_this_query(), a zero value keeps the default of tantivy.
+    struct MoreLikeThisParam{
+        min_term_frequency: usize, // min frequency of a term in the source document, default 2
+        min_doc_frequency: u64, // min number of documents containing a term, default 5
+        max_doc_frequency: u64, // max number of documents containing a term, default unlimited
+        max_query_terms: usize, // number of best terms kept in the query, default 25
+        min_word_length: usize,
+        max_word_length: usize,
+        boost_factor: f32, // boost of the terms by their tf-idf weight, default 1.0
+        stop_words: Vec<String>, // terms ignored
+    }
+
+    /// Byte range of a matched term in Snippet.fragment.
+    struct HighlightRange{
+        start: usize,
+        end: usize,
+    }
+
+    struct Snippet{
+        field_name: String,
+        fragment: String, // best fragment of the field value, empty if no term matched in the field
+        highlights: Vec<HighlightRange>,
+        html: String, // fragment with escaped text and highlighted terms, empty for SnippetFormat::ranges
     }
     
     struct IndexParam{
         memory_mbytes: usize,
+        analyzers: Vec<AnalyzerConfig>, // registered before the writer is created, so FieldMappings can refer to them
+        synonyms: Vec<SynonymGroup>,
+        synonyms_path: String, // synonym file loaded in addition to synonyms, see load_synonyms(), empty for none
+    }
+
+    /// Terms searched for each other, e.g. "tv" and "television", by search() and term_query().
+    struct SynonymGroup{
+        terms: Vec<String>,
+        boosts: Vec<f32>, // boost of each term when it is added as synonym of another term, empty for 1.0
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    enum BaseTokenizer {
+        simple_tokenizer = 0, // splits on non alphanumeric chars, as the "default" tokenizer
+        raw_tokenizer = 1, // the whole text is one token
+        ngram_tokenizer = 2,
+    }
+
+    /// Named analyzer built from tantivy's tokenizers and token filters, the filters are applied in
+    /// this order: remove_long, lowercase, ascii_folding, stop_words, stemmer_language.
+    struct AnalyzerConfig{
+        name: String,
+        tokenizer: BaseTokenizer,
+        ngram_min: usize, // ngram_tokenizer only
+        ngram_max: usize, // ngram_tokenizer only
+        ngram_prefix_only: bool, // ngram_tokenizer only, only the ngrams starting at the beginning of the text
+        remove_long: usize, // drop tokens longer than remove_long bytes, 0 to keep all tokens
+        lowercase: bool,
+        ascii_folding: bool,
+        stop_words: Vec<String>,
+        stemmer_language: String, // e.g. "english", "german", "french", empty for no stemming
     }
 
     #[derive(Debug, Clone, Copy)]
@@ -103,12 +292,24 @@ mod ffi {
         value:i64,
     }
 
+    struct ULongBound{
+        bound:RangeBound,
+        value:u64,
+    }
+
+    struct BytesBound{
+        bound:RangeBound,
+        value:Vec<u8>,
+    }
+
     #[derive(Debug)]
     struct IdDocument{
         docId: i64, // i64 instead of u64 for easier query against _doctId field by term_query_long()
         // title: String,
         fieldValues: Vec<DocumentField>,
         score: f32, // score for matched document 
+        sortValues: Vec<String>, // values of SearchParam.sort_by the document was ranked on
+        snippets: Vec<Snippet>, // one per SearchParam.snippet.fields, in the same order
     }
 
     /// Defines whether a term in a query must be present,
@@ -126,6 +327,21 @@ mod ffi {
         MustNot,
     }
     
+    /// Token produced by a C++ tokenizer, the offsets are byte offsets into the tokenized text.
+    struct CppToken{
+        offset_from: usize,
+        offset_to: usize,
+        text: String,
+    }
+
+    unsafe extern "C++" {
+        include!("tantivy-cpp-lib/include/cpp_tokenizer.h");
+
+        type CppTokenizer;
+
+        fn tokenize(self: &CppTokenizer, text: &str) -> Vec<CppToken>;
+    }
+
     extern "Rust" {
         type TQuery;
 
@@ -136,6 +352,12 @@ mod ffi {
         fn query_occur_vec() -> Box<TQueryOccurVec>;
 
         fn append_query_occur_to_vec(occurs_vec: & mut TQueryOccurVec, query_occur: & mut TQueryOccur);
+
+        type TQueryVec;
+
+        fn query_vec() -> Box<TQueryVec>;
+
+        fn append_query_to_vec(queries: & mut TQueryVec, query: & TQuery);
     }
 
     // definition of Rust interface 
@@ -144,18 +366,65 @@ mod ffi {
         
         type SearchResultBitmap;
 
+        type BitmapCursor;
+
         fn create_searcher(path: &String, field_mappings:Vec<FieldMapping>) -> Result<Box<Searcher>>;
         
         fn create_searcher_with_param(path: &String, field_mappings:Vec<FieldMapping>, param: IndexParam) -> Result<Box<Searcher>>;
         
+        fn validate_field_mappings(path: &String, field_mappings:Vec<FieldMapping>) -> Result<Vec<SchemaDiff>>;
+
+        fn get_field_mappings(searcher: & mut Searcher) -> Vec<FieldMapping>;
+
+        // fails on unstored fields (str_field by default), unless skip_unstored_fields leaves them empty in the new index
+        fn reindex_with_field_mappings(searcher: & mut Searcher, target_path: &String, field_mappings:Vec<FieldMapping>, param: IndexParam, skip_unstored_fields: bool) -> Result<Box<Searcher>>;
+
         fn search_compact_all(searcher: & mut Searcher, query: & TQuery) -> Result<Box<SearchResultBitmap>>;
         
         fn num_docs(searcher: & mut Searcher) -> Result<u64>;
 
-        fn is_member(result_map: & mut SearchResultBitmap, doc_id: u64) -> Result<bool>;
+        fn count_by_query(searcher: & mut Searcher, query: & TQuery) -> Result<u64>;
+
+        fn count_by_queries(searcher: & mut Searcher, queries: & TQueryVec) -> Result<Vec<u64>>;
+
+        fn is_member(result_map: & mut SearchResultBitmap, doc_id: i64) -> Result<bool>;
+
+        fn bitmap_len(result_map: & SearchResultBitmap) -> u64;
+
+        fn bitmap_min(result_map: & SearchResultBitmap) -> Result<i64>;
+
+        fn bitmap_max(result_map: & SearchResultBitmap) -> Result<i64>;
+
+        fn bitmap_and(left: & SearchResultBitmap, right: & SearchResultBitmap) -> Box<SearchResultBitmap>;
+
+        fn bitmap_or(left: & SearchResultBitmap, right: & SearchResultBitmap) -> Box<SearchResultBitmap>;
+
+        fn bitmap_andnot(left: & SearchResultBitmap, right: & SearchResultBitmap) -> Box<SearchResultBitmap>;
+
+        fn bitmap_xor(left: & SearchResultBitmap, right: & SearchResultBitmap) -> Box<SearchResultBitmap>;
+
+        fn bitmap_values(result_map: & SearchResultBitmap, from_doc_id: i64, max_count: usize, doc_ids: & mut Vec<i64>) -> usize;
+
+        fn bitmap_cursor(result_map: & SearchResultBitmap, from_doc_id: i64) -> Box<BitmapCursor>;
+
+        fn bitmap_cursor_next(cursor: & mut BitmapCursor, max_count: usize, doc_ids: & mut Vec<i64>) -> usize;
+
+        // bytes in the internal encoding of the members, only for bitmap_deserialize()
+        fn bitmap_serialize(result_map: & SearchResultBitmap) -> Result<Vec<u8>>;
+
+        // the bytes must come from bitmap_serialize(), use bitmap_deserialize_doc_ids() for a RoaringTreemap of plain docIds
+        fn bitmap_deserialize(bytes: & [u8]) -> Result<Box<SearchResultBitmap>>;
+
+        fn bitmap_serialize_doc_ids(result_map: & SearchResultBitmap) -> Result<Vec<u8>>;
+
+        fn bitmap_deserialize_doc_ids(bytes: & [u8]) -> Result<Box<SearchResultBitmap>>;
+
+        fn bitmap_from_doc_ids(doc_ids: & Vec<i64>) -> Box<SearchResultBitmap>;
 
         fn add_document(searcher: &mut Searcher, docs:Vec<IdDocument>, commit: bool) -> Result<()>;
 
+        fn upsert_document(searcher: &mut Searcher, docs:Vec<IdDocument>, commit: bool) -> Result<u64>;
+
         fn search(searcher: & mut Searcher, query: &String, search_fields: & Vec<String>, search_param: & SearchParam) -> Result<Vec<IdDocument>>;
 
         fn search_by_query(searcher: & mut Searcher, query: & TQuery, search_param: & SearchParam) -> Result<Vec<IdDocument>>;
@@ -164,19 +433,71 @@ mod ffi {
 
         fn term_query_long(searcher: &mut Searcher, field_name: &String, field_value: i64) -> Result<Box<TQuery>>;
 
+        fn term_query_ulong(searcher: &mut Searcher, field_name: &String, field_value: u64) -> Result<Box<TQuery>>;
+
+        fn term_query_bytes(searcher: &mut Searcher, field_name: &String, field_value: &Vec<u8>) -> Result<Box<TQuery>>;
+
+        fn term_set_query(searcher: &mut Searcher, field_name: &String, field_values: &Vec<String>) -> Result<Box<TQuery>>;
+
+        fn term_set_query_long(searcher: &mut Searcher, field_name: &String, field_values: &Vec<i64>) -> Result<Box<TQuery>>;
+
         fn range_query(searcher: &mut Searcher, field_name: &String, from_value: &StringBound, to_value: &StringBound) -> Result<Box<TQuery>>;
 
         fn range_query_float(searcher: &mut Searcher, field_name: &String, from_value: &FloatBound, to_value: &FloatBound) -> Result<Box<TQuery>> ;
 
         fn range_query_long(searcher: &mut Searcher, field_name: &String, from_value: &LongBound, to_value: &LongBound) -> Result<Box<TQuery>>;
+
+        fn range_query_date(searcher: &mut Searcher, field_name: &String, from_value: &StringBound, to_value: &StringBound) -> Result<Box<TQuery>>;
+
+        fn range_query_ulong(searcher: &mut Searcher, field_name: &String, from_value: &ULongBound, to_value: &ULongBound) -> Result<Box<TQuery>>;
+
+        fn range_query_bytes(searcher: &mut Searcher, field_name: &String, from_value: &BytesBound, to_value: &BytesBound) -> Result<Box<TQuery>>;
+
+        fn term_query_json(searcher: &mut Searcher, field_path: &String, field_value: &String) -> Result<Box<TQuery>>;
+
+        fn range_query_json(searcher: &mut Searcher, field_path: &String, from_value: &StringBound, to_value: &StringBound) -> Result<Box<TQuery>>;
+
+        fn phrase_query(searcher: &mut Searcher, field_name: &String, phrase: &String, slop: u32) -> Result<Box<TQuery>>;
+
+        fn fuzzy_term_query(searcher: &mut Searcher, field_name: &String, field_value: &String, distance: u8, transposition_cost_one: bool) -> Result<Box<TQuery>>;
+
+        fn prefix_query(searcher: &mut Searcher, field_name: &String, prefix: &String) -> Result<Box<TQuery>>;
+
+        fn regex_query(searcher: &mut Searcher, field_name: &String, pattern: &String) -> Result<Box<TQuery>>;
      
+        fn all_query() -> Box<TQuery>;
+
+        fn more_like_this_query(searcher: &mut Searcher, doc_id: i64, fields: &Vec<String>, params: &MoreLikeThisParam) -> Result<Box<TQuery>>;
+
+        fn more_like_this_text_query(searcher: &mut Searcher, text: &String, fields: &Vec<String>, params: &MoreLikeThisParam) -> Result<Box<TQuery>>;
+
+        fn exists_query(searcher: &mut Searcher, field_name: &String) -> Result<Box<TQuery>>;
+
         fn query_occurr(occurr: & TOccur, query: & mut TQuery) -> Box<TQueryOccur>;
 
         fn boolean_query(queries: & TQueryOccurVec ) -> Result<Box<TQuery>>;
+
+        fn doc_id_set_query(query: & TQuery, doc_ids: & SearchResultBitmap) -> Box<TQuery>;
+
+        fn boost_query(query: & TQuery, factor: f32) -> Box<TQuery>;
+
+        fn const_score_query(query: & TQuery, score: f32) -> Box<TQuery>;
+
+        fn disjunction_max_query(queries: & TQueryVec, tie_breaker: f32) -> Result<Box<TQuery>>;
         
         fn delete_document(searcher: &mut Searcher, doc_ids:Vec<i64>, commit: bool) -> Result<()>;
 
+        fn delete_by_query(searcher: &mut Searcher, query: & TQuery, commit: bool) -> Result<u64>;
+
         pub fn commit_index(searcher: &mut Searcher)  -> Result<()>;
+
+        fn register_analyzer(searcher: &mut Searcher, analyzer_config: &AnalyzerConfig) -> Result<()>;
+
+        fn set_synonyms(searcher: &mut Searcher, synonym_groups: Vec<SynonymGroup>) -> Result<()>;
+
+        fn load_synonyms(searcher: &mut Searcher, path: &String) -> Result<()>;
+
+        fn register_cpp_tokenizer(searcher: &mut Searcher, name: &String, tokenizer: SharedPtr<CppTokenizer>) -> Result<()>;
     }
     extern "Rust" {
         fn rust_from_cpp() -> ();
@@ -206,6 +527,9 @@ impl std::fmt::Display for TQuery {
 pub struct Searcher{
     _index_path: String,
     schema: Schema,
+    field_types: HashMap<String, FieldType>, // FieldType of each mapped field, used for returned field values
+    search_tokenizers: HashMap<String, String>, // search analyzer of the fields whose FieldMapping.search_tokenizer is set
+    synonyms: HashMap<String, Vec<(String, f32)>>, // lowercased term -> synonyms with their boost
     index_writer: IndexWriter,
     index_reader: IndexReader,
 }
@@ -214,21 +538,38 @@ pub struct SearchResultBitmap{
     bitmap:  RoaringTreemap,
 }
 
+/// Position in a snapshot of a SearchResultBitmap, see bitmap_cursor().
+pub struct BitmapCursor{
+    members: roaring::treemap::IntoIter,
+}
+
 #[derive(Debug)]
 pub struct TQuery{
     query: Box<dyn Query>,
+    doc_id_filter: Option<Arc<RoaringTreemap>>, // set by doc_id_set_query(), applied in the segment collectors
+}
+
+impl Clone for TQuery {
+    fn clone(&self) -> Self {
+        TQuery{ query: self.query.box_clone(), doc_id_filter: self.doc_id_filter.clone() }
+    }
 }
 
 #[derive(Debug)]
 pub struct TQueryOccur{
     occur: TOccur,
     query: Box<dyn Query>,
+    doc_id_filter: Option<Arc<RoaringTreemap>>,
 }
 
 pub struct TQueryOccurVec {
     occurs: Vec<TQueryOccur>,
 }
 
+pub struct TQueryVec {
+    queries: Vec<TQuery>,
+}
+
 pub fn term_query(searcher: &mut Searcher, field_name: &String, field_value: &String) -> Result<Box<TQuery>, Box<dyn Error>> {
     let field_option = searcher.schema.get_field(field_name);
 
@@ -238,10 +579,42 @@ pub fn term_query(searcher: &mut Searcher, field_name: &String, field_value: &St
 
     let field = field_option.unwrap();
 
-    let tq = TQuery{ query: Box::new( TermQuery::new(
+    let term_query = TermQuery::new(
         Term::from_field_text(field, field_value),
         IndexRecordOption::Basic,
-    ))};
+    );
+
+    // a term with synonyms matches any of them, the synonyms scored with their boost. The synonyms are
+    // analyzed like the indexed text, so "Flat Screen" matches the phrase "flat screen" of a text field
+    if let Some(synonyms) = searcher.synonyms.get(&field_value.to_lowercase()) {
+        let field_entry = searcher.schema.get_field_entry(field);
+        let indexing = match field_entry.field_type() {
+            tantivy::schema::FieldType::Str(text_options) => text_options.get_indexing_options(),
+            _ => None,
+        };
+
+        let analyzer = match indexing {
+            Some(_) => Some(searcher.index_writer.index().tokenizer_for_field(field)?),
+            None => None,
+        };
+
+        let mut queries_with_occur: Vec<(Occur, Box<dyn Query>)> = vec![(Occur::Should, Box::new(term_query))];
+        for (synonym, boost) in synonyms {
+            let query: Box<dyn Query> = match (&analyzer, indexing) {
+                (Some(analyzer), Some(indexing)) => {
+                    match synonym_query(analyze_terms(analyzer, field, synonym), indexing.index_option().has_positions()) {
+                        Some(query) => query,
+                        None => continue,
+                    }
+                }
+                _ => Box::new(TermQuery::new(Term::from_field_text(field, synonym), IndexRecordOption::Basic)),
+            };
+            queries_with_occur.push((Occur::Should, Box::new(BoostQuery::new(query, *boost))));
+        }
+        return Ok(Box::new(TQuery{ query: Box::new(BooleanQuery::new(queries_with_occur)), doc_id_filter: None }));
+    }
+
+    let tq = TQuery{ query: Box::new(term_query), doc_id_filter: None };
 
     return Ok(Box::new(tq));
 }
@@ -258,7 +631,74 @@ pub fn term_query_long(searcher: &mut Searcher, field_name: &String, field_value
     let tq = TQuery{ query: Box::new( TermQuery::new(
         Term::from_field_i64(field, field_value),
         IndexRecordOption::Basic,
-    ))};
+    )), doc_id_filter: None };
+
+    return Ok(Box::new(tq));
+}
+
+/// Matches the documents having any of the values, e.g. a category in a list of categories.
+/// Built in one call and run as a single query instead of a boolean_query of term_querys.
+pub fn term_set_query(searcher: &mut Searcher, field_name: &String, field_values: &Vec<String>) -> Result<Box<TQuery>, Box<dyn Error>> {
+    let field_option = searcher.schema.get_field(field_name);
+
+    if field_option.is_err(){
+        bail!(format!("field {field_name} not found! "));
+    }
+
+    let field = field_option.unwrap();
+
+    let terms = field_values.iter().map(|field_value| Term::from_field_text(field, field_value));
+    let tq = TQuery{ query: Box::new(TermSetQuery::new(terms)), doc_id_filter: None };
+
+    return Ok(Box::new(tq));
+}
+
+/// Matches the documents having any of the long values, e.g. _docId in a list of ids.
+pub fn term_set_query_long(searcher: &mut Searcher, field_name: &String, field_values: &Vec<i64>) -> Result<Box<TQuery>, Box<dyn Error>> {
+    let field_option = searcher.schema.get_field(field_name);
+
+    if field_option.is_err(){
+        bail!(format!("field {field_name} not found! "));
+    }
+
+    let field = field_option.unwrap();
+
+    let terms = field_values.iter().map(|field_value| Term::from_field_i64(field, *field_value));
+    let tq = TQuery{ query: Box::new(TermSetQuery::new(terms)), doc_id_filter: None };
+
+    return Ok(Box::new(tq));
+}
+
+pub fn term_query_ulong(searcher: &mut Searcher, field_name: &String, field_value: u64) -> Result<Box<TQuery>, Box<dyn Error>> {
+    let field_option = searcher.schema.get_field(field_name);
+
+    if field_option.is_err(){
+        bail!(format!("field {field_name} not found! "));
+    }
+
+    let field = field_option.unwrap();
+
+    let tq = TQuery{ query: Box::new( TermQuery::new(
+        Term::from_field_u64(field, field_value),
+        IndexRecordOption::Basic,
+    )), doc_id_filter: None };
+
+    return Ok(Box::new(tq));
+}
+
+pub fn term_query_bytes(searcher: &mut Searcher, field_name: &String, field_value: &Vec<u8>) -> Result<Box<TQuery>, Box<dyn Error>> {
+    let field_option = searcher.schema.get_field(field_name);
+
+    if field_option.is_err(){
+        bail!(format!("field {field_name} not found! "));
+    }
+
+    let field = field_option.unwrap();
+
+    let tq = TQuery{ query: Box::new( TermQuery::new(
+        Term::from_field_bytes(field, field_value),
+        IndexRecordOption::Basic,
+    )), doc_id_filter: None };
 
     return Ok(Box::new(tq));
 }
@@ -289,7 +729,7 @@ pub fn range_query(searcher: &mut Searcher, field_name: &String, from_value: &St
     let tq = TQuery{ query: Box::new(
         // RangeQuery::new_str(field, &from_value.value..&to_value.value)
         RangeQuery::new_str_bounds(field_name.clone(), left, right)
-     )};
+     ), doc_id_filter: None };
 
     return Ok(Box::new(tq));
 }
@@ -318,7 +758,7 @@ pub fn range_query_float(searcher: &mut Searcher, field_name: &String, from_valu
     let tq = TQuery{ query: Box::new(
         // RangeQuery::new_f64(field, from_value.value..to_value.value)
         RangeQuery::new_f64_bounds(field_name.clone(), left, right)
-     )};
+     ), doc_id_filter: None };
 
     return Ok(Box::new(tq));
 }
@@ -348,254 +788,2149 @@ pub fn range_query_long(searcher: &mut Searcher, field_name: &String, from_value
     let tq = TQuery{ query: Box::new(
         // RangeQuery::new_i64(field, from_value.value..to_value.value)
         RangeQuery::new_i64_bounds(field_name.clone(), left, right)
-     )};
+     ), doc_id_filter: None };
 
     return Ok(Box::new(tq));
 }
 
+pub fn range_query_ulong(searcher: &mut Searcher, field_name: &String, from_value: &ULongBound, to_value: &ULongBound) -> Result<Box<TQuery>, Box<dyn Error>> {
+    let field_option = searcher.schema.get_field(field_name);
 
-pub fn boolean_query(queries: & TQueryOccurVec ) -> Result<Box<TQuery>, Box<dyn Error>> {
-    let mut queries_with_occur: Vec<(Occur, Box<dyn Query>)> = Vec::new();
-
-    for query in & queries.occurs {
-        match query.occur{
-            TOccur::Must => queries_with_occur.push((Occur::Must, query.query.box_clone())),
-            TOccur::MustNot => queries_with_occur.push((Occur::MustNot, query.query.box_clone())),
-            TOccur::Should => queries_with_occur.push((Occur::Should, query.query.box_clone())),
-            _ => log::warn!("Not supported occur!"),
-        }
+    if field_option.is_err(){
+        bail!(format!("field {field_name} not found! "));
     }
 
-    let tq = TQuery{ query: Box::new(BooleanQuery::new(queries_with_occur))};
+    let left: Bound<u64> = match from_value.bound{
+        RangeBound::Included => Bound::Included(from_value.value),
+        RangeBound::Excluded => Bound::Excluded(from_value.value),
+        _ => Bound::Unbounded,
+    };
+
+    let right: Bound<u64> = match to_value.bound{
+        RangeBound::Included => Bound::Included(to_value.value),
+        RangeBound::Excluded => Bound::Excluded(to_value.value),
+        _ => Bound::Unbounded,
+    };
+
+    let tq = TQuery{ query: Box::new(
+        RangeQuery::new_u64_bounds(field_name.clone(), left, right)
+     ), doc_id_filter: None };
 
     return Ok(Box::new(tq));
 }
 
-pub fn query_occurr(occurr: & TOccur, tquery: & mut TQuery) -> Box<TQueryOccur> {
-    let query_occur = TQueryOccur{occur: *occurr, query: tquery.query.box_clone()};
-    return Box::new(query_occur);
-}
+/// Range query on a bytes_field, the bytes are compared lexicographically.
+pub fn range_query_bytes(searcher: &mut Searcher, field_name: &String, from_value: &BytesBound, to_value: &BytesBound) -> Result<Box<TQuery>, Box<dyn Error>> {
+    let field_option = searcher.schema.get_field(field_name);
 
-pub fn query_occur_vec() -> Box<TQueryOccurVec>{
-    return Box::new(TQueryOccurVec{occurs: Vec::new()});
-}
+    if field_option.is_err(){
+        bail!(format!("field {field_name} not found! "));
+    }
 
+    let field = field_option.unwrap();
 
-pub fn append_query_occur_to_vec(occurs_vec: & mut TQueryOccurVec, query_occur: & mut TQueryOccur) {
-    let t = TQueryOccur{occur: query_occur.occur, query: query_occur.query.box_clone()};
-    occurs_vec.occurs.push(t);
-}
+    let left: Bound<Term> = match from_value.bound{
+        RangeBound::Included => Bound::Included(Term::from_field_bytes(field, &from_value.value)),
+        RangeBound::Excluded => Bound::Excluded(Term::from_field_bytes(field, &from_value.value)),
+        _ => Bound::Unbounded,
+    };
 
+    let right: Bound<Term> = match to_value.bound{
+        RangeBound::Included => Bound::Included(Term::from_field_bytes(field, &to_value.value)),
+        RangeBound::Excluded => Bound::Excluded(Term::from_field_bytes(field, &to_value.value)),
+        _ => Bound::Unbounded,
+    };
 
-pub fn create_searcher(path: &String, field_mappings:Vec<FieldMapping>) -> Result<Box<Searcher>, Box<dyn Error>>{
-    create_searcher_with_param(path, field_mappings, IndexParam{memory_mbytes: 256})
-}
+    let tq = TQuery{ query: Box::new(
+        RangeQuery::new_term_bounds(field_name.clone(), Type::Bytes, &left, &right)
+     ), doc_id_filter: None };
 
-pub fn create_searcher_with_param(path: &String, field_mappings:Vec<FieldMapping>, param: IndexParam) -> Result<Box<Searcher>, Box<dyn Error>>
-{
-    std::fs::create_dir_all("logs")?;
-    _ = simple_logging::log_to_file("logs/tantivy_index.log", LevelFilter::Info);
-    log::info!("Rust logging initialized");
+    return Ok(Box::new(tq));
+}
 
-    let index_dir = std::path::Path::new(path);
-    let index_path = index_dir;
+/// Matches all the documents, e.g. for search_compact_all() to return every docId.
+pub fn all_query() -> Box<TQuery> {
+    return Box::new(TQuery{ query: Box::new(AllQuery), doc_id_filter: None });
+}
 
-    std::fs::create_dir_all(index_path)?; // create dir if not exist
+/// Documents similar to the stored fields of the document with the docId, the document itself is excluded.
+/// The fields must be stored, empty fields select all the stored text fields of the document.
+pub fn more_like_this_query(searcher: &mut Searcher, doc_id: i64, fields: &Vec<String>, params: &MoreLikeThisParam) -> Result<Box<TQuery>, Box<dyn Error>> {
+    let index_searcher = searcher.index_reader.searcher();
+    let doc_id_field = searcher.schema.get_field("_docId").unwrap();
+    let doc_id_query = TermQuery::new(Term::from_field_i64(doc_id_field, doc_id), IndexRecordOption::Basic);
 
-    index_path.to_str().expect("index_path should not be empty");
+    let top_docs = index_searcher.search(&doc_id_query, &TopDocs::with_limit(1))?;
+    if top_docs.is_empty() {
+        bail!(format!("document {doc_id} not found! "));
+    }
+    let retrieved_doc = index_searcher.doc(top_docs[0].1)?;
 
-    let mut schema_builder = Schema::builder();
+    let mut source_fields: Vec<Field> = Vec::new();
+    if fields.is_empty() {
+        for (field, field_entry) in searcher.schema.fields() {
+            if field_entry.is_stored() && matches!(field_entry.field_type(), tantivy::schema::FieldType::Str(_)) {
+                source_fields.push(field);
+            }
+        }
+    } else {
+        source_fields = more_like_this_fields(searcher, fields)?;
 
-    // set the _docId to be INDEXED for query & delete
-    schema_builder.add_i64_field("_docId", NumericOptions::default() | STORED | INDEXED | FAST);
-    for field_mapping in field_mappings {
+        // the values are read from the doc store, an unstored field would silently add no terms
+        for field in &source_fields {
+            if !searcher.schema.get_field_entry(*field).is_stored() {
+                bail!(format!("field {} is not stored and can not be used by more_like_this_query! ", searcher.schema.get_field_name(*field)));
+            }
+        }
+    }
 
-        let _ = match field_mapping.field_type{
-            FieldType::int_field  => schema_builder.add_i64_field(&field_mapping.field_name, NumericOptions::default() | STORED | INDEXED),
-            FieldType::long_field => schema_builder.add_i64_field(&field_mapping.field_name, NumericOptions::default() | STORED | INDEXED),
-            FieldType::float_field => schema_builder.add_f64_field(&field_mapping.field_name, NumericOptions::default() | STORED | INDEXED ),
-            FieldType::double_field => schema_builder.add_f64_field(&field_mapping.field_name, NumericOptions::default() | STORED | INDEXED),
-            FieldType::str_field => schema_builder.add_text_field(&field_mapping.field_name, STRING),
-            FieldType::bool_field => schema_builder.add_bool_field(&field_mapping.field_name, STORED | INDEXED),
-            FieldType::text_field => schema_builder.add_text_field(&field_mapping.field_name, TEXT | STORED),
-
-            _ => schema_builder.add_text_field(&field_mapping.field_name, STRING),
-        };
+    let mut document_fields: Vec<(Field, Vec<Value>)> = Vec::new();
+    for field in source_fields {
+        let values: Vec<Value> = retrieved_doc.get_all(field).cloned().collect();
+        if !values.is_empty() {
+            document_fields.push((field, values));
+        }
     }
 
-    let schema = schema_builder.build();
+    let more_like_this = more_like_this_builder(params).with_document_fields(document_fields);
+    let queries_with_occur: Vec<(Occur, Box<dyn Query>)> = vec![
+        (Occur::Must, Box::new(more_like_this)),
+        (Occur::MustNot, Box::new(doc_id_query)),
+    ];
 
-    //let index = Index::create_in_dir(&index_path, schema.clone())?;
-    let mmap_directory = MmapDirectory::open(index_path)?;
-    let index = Index::open_or_create(mmap_directory, schema.clone())?;
+    return Ok(Box::new(TQuery{ query: Box::new(BooleanQuery::new(queries_with_occur)), doc_id_filter: None }));
+}
 
-    let index_writer = index.writer(param.memory_mbytes * 1000_000)?;
+/// Documents similar to a free text, the text is analyzed as a value of each of the fields.
+pub fn more_like_this_text_query(searcher: &mut Searcher, text: &String, fields: &Vec<String>, params: &MoreLikeThisParam) -> Result<Box<TQuery>, Box<dyn Error>> {
+    if fields.is_empty() {
+        bail!("more_like_this_text_query needs at least one field");
+    }
 
-    let mut merge_policy = LogMergePolicy::default();
-    merge_policy.set_max_docs_before_merge(320000);
-    merge_policy.set_min_num_segments(3);
-    merge_policy.set_min_layer_size(60000);
+    let document_fields: Vec<(Field, Vec<Value>)> = more_like_this_fields(searcher, fields)?
+        .into_iter()
+        .map(|field| (field, vec![Value::Str(text.clone())]))
+        .collect();
+
+    let more_like_this = more_like_this_builder(params).with_document_fields(document_fields);
+
+    return Ok(Box::new(TQuery{ query: Box::new(more_like_this), doc_id_filter: None }));
+}
+
+fn more_like_this_fields(searcher: &Searcher, fields: &Vec<String>) -> Result<Vec<Field>, Box<dyn Error>> {
+    let mut more_like_this_fields: Vec<Field> = Vec::new();
+
+    for field_name in fields {
+        match searcher.schema.get_field(field_name) {
+            Ok(field) => {
+                if !searcher.schema.get_field_entry(field).is_indexed() {
+                    bail!(format!("field {field_name} is not indexed! "));
+                }
+                more_like_this_fields.push(field);
+            }
+            Err(_) => {
+                bail!(format!("field {field_name} not found! "));
+            }
+        }
+    }
+
+    return Ok(more_like_this_fields);
+}
+
+fn more_like_this_builder(params: &MoreLikeThisParam) -> MoreLikeThisQueryBuilder {
+    let mut builder = MoreLikeThisQuery::builder();
+
+    if params.min_term_frequency > 0 {
+        builder = builder.with_min_term_frequency(params.min_term_frequency);
+    }
+    if params.min_doc_frequency > 0 {
+        builder = builder.with_min_doc_frequency(params.min_doc_frequency);
+    }
+    if params.max_doc_frequency > 0 {
+        builder = builder.with_max_doc_frequency(params.max_doc_frequency);
+    }
+    if params.max_query_terms > 0 {
+        builder = builder.with_max_query_terms(params.max_query_terms);
+    }
+    if params.min_word_length > 0 {
+        builder = builder.with_min_word_length(params.min_word_length);
+    }
+    if params.max_word_length > 0 {
+        builder = builder.with_max_word_length(params.max_word_length);
+    }
+    if params.boost_factor > 0.0 {
+        builder = builder.with_boost_factor(params.boost_factor);
+    }
+    if !params.stop_words.is_empty() {
+        builder = builder.with_stop_words(params.stop_words.clone());
+    }
+
+    return builder;
+}
+
+/// Matches the documents having a value for the field, with a term query on the _fields marker.
+/// Indexes created before the marker existed fall back to an unbounded range over the terms of the field,
+/// which walks the whole term dictionary of the field, so the field must be indexed and should have few terms.
+pub fn exists_query(searcher: &mut Searcher, field_name: &String) -> Result<Box<TQuery>, Box<dyn Error>> {
+    let field_option = searcher.schema.get_field(field_name);
+
+    if field_option.is_err(){
+        bail!(format!("field {field_name} not found! "));
+    }
+
+    let field = field_option.unwrap();
+
+    if let Ok(fields_field) = searcher.schema.get_field("_fields") {
+        let tq = TQuery{ query: Box::new( TermQuery::new(
+            Term::from_field_text(fields_field, field_name),
+            IndexRecordOption::Basic,
+        )), doc_id_filter: None };
+
+        return Ok(Box::new(tq));
+    }
+
+    let field_type = searcher.schema.get_field_entry(field).field_type();
+
+    // single-valued fast fields hold a default value for documents without value, so they can't tell presence
+    if !field_type.is_indexed() {
+        bail!(format!("field {field_name} must be indexed for exists_query! "));
+    }
+
+    let tq = TQuery{ query: Box::new(
+        RangeQuery::new_term_bounds(field_name.clone(), field_type.value_type(), &Bound::Unbounded, &Bound::Unbounded)
+     ), doc_id_filter: None };
+
+    return Ok(Box::new(tq));
+}
+
+/// Term query on a dotted path of a json_field, e.g. "attrs.color" for the key color of the json_field attrs.
+/// The value matches the string values of the path as a whole, it is not analyzed, like term_query().
+/// A value that parses as a number also matches the integer and float values of the path.
+pub fn term_query_json(searcher: &mut Searcher, field_path: &String, field_value: &String) -> Result<Box<TQuery>, Box<dyn Error>> {
+    let (field, json_path, expand_dots) = json_path_field(searcher, field_path)?;
+
+    let mut terms: Vec<Term> = vec![json_term(field, &json_path, expand_dots, &|writer| writer.set_str(field_value))];
+    if let Ok(number) = field_value.parse::<i64>() {
+        terms.push(json_term(field, &json_path, expand_dots, &|writer| writer.set_fast_value(number)));
+    }
+    if let Ok(number) = field_value.parse::<f64>() {
+        terms.push(json_term(field, &json_path, expand_dots, &|writer| writer.set_fast_value(number)));
+    }
+
+    let queries_with_occur: Vec<(Occur, Box<dyn Query>)> = terms.into_iter()
+        .map(|term| -> (Occur, Box<dyn Query>) { (Occur::Should, Box::new(TermQuery::new(term, IndexRecordOption::Basic))) })
+        .collect();
+
+    return Ok(Box::new(TQuery{ query: Box::new(BooleanQuery::new(queries_with_occur)), doc_id_filter: None }));
+}
+
+/// Range query on a dotted path of a json_field. When both bound values are numbers, the integer and float
+/// values of the path are compared as numbers, otherwise the string values are compared as strings.
+///
+/// The json terms of the bounds are built here, the QueryParser rejects ranges on json fields.
+pub fn range_query_json(searcher: &mut Searcher, field_path: &String, from_value: &StringBound, to_value: &StringBound) -> Result<Box<TQuery>, Box<dyn Error>> {
+    let (field, json_path, expand_dots) = json_path_field(searcher, field_path)?;
+    let field_name = searcher.schema.get_field_name(field).to_string();
+
+    let bound_term = |set_value: &dyn Fn(&mut JsonTermWriter)| -> Term {
+        json_term(field, &json_path, expand_dots, set_value)
+    };
+
+    let is_string = |bound: &StringBound| -> bool {
+        matches!(bound.bound, RangeBound::Included | RangeBound::Excluded) && bound.value.parse::<f64>().is_err()
+    };
+
+    // string values, the unbounded sides stay within the string terms of the path
+    if is_string(from_value) || is_string(to_value) {
+        let left: Bound<Term> = match from_value.bound{
+            RangeBound::Included => Bound::Included(bound_term(&|writer| writer.set_str(&from_value.value))),
+            RangeBound::Excluded => Bound::Excluded(bound_term(&|writer| writer.set_str(&from_value.value))),
+            _ => Bound::Included(bound_term(&|writer| writer.set_str(""))),
+        };
+
+        let right: Bound<Term> = match to_value.bound{
+            RangeBound::Included => Bound::Included(bound_term(&|writer| writer.set_str(&to_value.value))),
+            RangeBound::Excluded => Bound::Excluded(bound_term(&|writer| writer.set_str(&to_value.value))),
+            _ => Bound::Included(bound_term(&|writer| writer.set_str("\u{10FFFF}\u{10FFFF}\u{10FFFF}\u{10FFFF}"))),
+        };
+
+        let tq = TQuery{ query: Box::new(
+            RangeQuery::new_term_bounds(field_name, Type::Json, &left, &right)
+         ), doc_id_filter: None };
+
+        return Ok(Box::new(tq));
+    }
+
+    let from_number = from_value.value.parse::<f64>().unwrap_or(f64::NEG_INFINITY);
+    let to_number = to_value.value.parse::<f64>().unwrap_or(f64::INFINITY);
+
+    let float_left: Bound<Term> = match from_value.bound{
+        RangeBound::Included => Bound::Included(bound_term(&|writer| writer.set_fast_value(from_number))),
+        RangeBound::Excluded => Bound::Excluded(bound_term(&|writer| writer.set_fast_value(from_number))),
+        _ => Bound::Included(bound_term(&|writer| writer.set_fast_value(f64::NEG_INFINITY))),
+    };
+
+    let float_right: Bound<Term> = match to_value.bound{
+        RangeBound::Included => Bound::Included(bound_term(&|writer| writer.set_fast_value(to_number))),
+        RangeBound::Excluded => Bound::Excluded(bound_term(&|writer| writer.set_fast_value(to_number))),
+        _ => Bound::Included(bound_term(&|writer| writer.set_fast_value(f64::INFINITY))),
+    };
+
+    // integers are indexed as i64 terms, n >= x is n >= ceil(x), n > x is n > floor(x) and so on
+    let integer_left: Bound<Term> = match from_value.bound{
+        RangeBound::Included => Bound::Included(bound_term(&|writer| writer.set_fast_value(from_number.ceil() as i64))),
+        RangeBound::Excluded => Bound::Excluded(bound_term(&|writer| writer.set_fast_value(from_number.floor() as i64))),
+        _ => Bound::Included(bound_term(&|writer| writer.set_fast_value(i64::MIN))),
+    };
+
+    let integer_right: Bound<Term> = match to_value.bound{
+        RangeBound::Included => Bound::Included(bound_term(&|writer| writer.set_fast_value(to_number.floor() as i64))),
+        RangeBound::Excluded => Bound::Excluded(bound_term(&|writer| writer.set_fast_value(to_number.ceil() as i64))),
+        _ => Bound::Included(bound_term(&|writer| writer.set_fast_value(i64::MAX))),
+    };
+
+    let queries_with_occur: Vec<(Occur, Box<dyn Query>)> = vec![
+        (Occur::Should, Box::new(RangeQuery::new_term_bounds(field_name.clone(), Type::Json, &float_left, &float_right))),
+        (Occur::Should, Box::new(RangeQuery::new_term_bounds(field_name, Type::Json, &integer_left, &integer_right))),
+    ];
+
+    return Ok(Box::new(TQuery{ query: Box::new(BooleanQuery::new(queries_with_occur)), doc_id_filter: None }));
+}
+
+/// Splits a dotted path into its json_field and the path within the json object,
+/// with the expand_dots option of the field that the json terms are built with.
+fn json_path_field(searcher: &Searcher, field_path: &String) -> Result<(Field, String, bool), Box<dyn Error>> {
+    let (field_name, json_path) = field_path.split_once('.').unwrap_or((field_path.as_str(), ""));
+
+    let field_option = searcher.schema.get_field(field_name);
+    if field_option.is_err(){
+        bail!(format!("field {field_name} not found! "));
+    }
+    let field = field_option.unwrap();
+
+    match searcher.schema.get_field_entry(field).field_type() {
+        tantivy::schema::FieldType::JsonObject(json_options) => {
+            return Ok((field, json_path.to_string(), json_options.is_expand_dots_enabled()));
+        }
+        _ => bail!(format!("field {field_name} is not a json field! ")),
+    }
+}
+
+/// Term of a json path, the value is set by set_value, e.g. with set_str() or set_fast_value().
+fn json_term(field: Field, json_path: &str, expand_dots: bool, set_value: &dyn Fn(&mut JsonTermWriter)) -> Term {
+    let mut term = Term::with_capacity(json_path.len() + 16);
+    let mut json_term_writer = JsonTermWriter::from_field_and_json_path(field, json_path, expand_dots, &mut term);
+    set_value(&mut json_term_writer);
+
+    return json_term_writer.term().clone();
+}
+
+/// Phrase query on a text_field, the phrase is tokenized by the analyzer of the field.
+/// slop is the number of position moves allowed between the terms, 0 for an exact phrase.
+/// The field must be indexed with RecordOption::positions.
+pub fn phrase_query(searcher: &mut Searcher, field_name: &String, phrase: &String, slop: u32) -> Result<Box<TQuery>, Box<dyn Error>> {
+    let field_option = searcher.schema.get_field(field_name);
+
+    if field_option.is_err(){
+        bail!(format!("field {field_name} not found! "));
+    }
+
+    let field = field_option.unwrap();
+
+    let has_positions = match searcher.schema.get_field_entry(field).field_type() {
+        tantivy::schema::FieldType::Str(text_options) => text_options.get_indexing_options()
+            .map(|indexing| indexing.index_option().has_positions())
+            .unwrap_or(false),
+        _ => false,
+    };
+    if !has_positions {
+        bail!(format!("field {field_name} is not indexed with positions! "));
+    }
+
+    let analyzer = searcher.index_writer.index().tokenizer_for_field(field)?;
+    let mut terms = analyze_terms(&analyzer, field, phrase);
+
+    if terms.is_empty() {
+        bail!(format!("phrase {phrase} has no terms! "));
+    }
+
+    // a PhraseQuery needs at least two terms
+    if terms.len() == 1 {
+        let (_, term) = terms.pop().unwrap();
+        return Ok(Box::new(TQuery{ query: Box::new(TermQuery::new(term, IndexRecordOption::WithFreqs)), doc_id_filter: None }));
+    }
+
+    let mut phrase_query = PhraseQuery::new_with_offset(terms);
+    phrase_query.set_slop(slop);
+
+    return Ok(Box::new(TQuery{ query: Box::new(phrase_query), doc_id_filter: None }));
+}
+
+/// Terms of a text analyzed like the values of the field, with their positions.
+fn analyze_terms(analyzer: &TextAnalyzer, field: Field, text: &str) -> Vec<(usize, Term)> {
+    let mut terms: Vec<(usize, Term)> = Vec::new();
+    analyzer.token_stream(text).process(&mut |token: &Token| {
+        terms.push((token.position, Term::from_field_text(field, &token.text)));
+    });
+
+    return terms;
+}
+
+/// Query of an analyzed synonym: a TermQuery for one term, a PhraseQuery for several terms,
+/// or all the terms when the field has no positions. None if the synonym has no terms.
+fn synonym_query(mut terms: Vec<(usize, Term)>, has_positions: bool) -> Option<Box<dyn Query>> {
+    if terms.len() <= 1 {
+        return terms.pop().map(|(_, term)| -> Box<dyn Query> { Box::new(TermQuery::new(term, IndexRecordOption::WithFreqs)) });
+    }
+
+    if has_positions {
+        return Some(Box::new(PhraseQuery::new_with_offset(terms)));
+    }
+
+    let queries_with_occur: Vec<(Occur, Box<dyn Query>)> = terms.into_iter()
+        .map(|(_, term)| -> (Occur, Box<dyn Query>) { (Occur::Must, Box::new(TermQuery::new(term, IndexRecordOption::Basic))) })
+        .collect();
+
+    return Some(Box::new(BooleanQuery::new(queries_with_occur)));
+}
+
+/// Term query matching the terms within a Levenshtein distance of the value, distance is at most 2.
+/// With transposition_cost_one, swapping two adjacent characters counts as one edit instead of two.
+/// The value is not analyzed, like term_query().
+pub fn fuzzy_term_query(searcher: &mut Searcher, field_name: &String, field_value: &String, distance: u8, transposition_cost_one: bool) -> Result<Box<TQuery>, Box<dyn Error>> {
+    let field_option = searcher.schema.get_field(field_name);
+
+    if field_option.is_err(){
+        bail!(format!("field {field_name} not found! "));
+    }
+
+    let field = field_option.unwrap();
+
+    if distance > 2 {
+        bail!(format!("fuzzy distance {distance} is greater than 2"));
+    }
+
+    let tq = TQuery{ query: Box::new( FuzzyTermQuery::new(
+        Term::from_field_text(field, field_value),
+        distance,
+        transposition_cost_one,
+    )), doc_id_filter: None };
+
+    return Ok(Box::new(tq));
+}
+
+/// Matches the terms starting with the prefix, the prefix is not analyzed, like term_query().
+pub fn prefix_query(searcher: &mut Searcher, field_name: &String, prefix: &String) -> Result<Box<TQuery>, Box<dyn Error>> {
+    let field_option = searcher.schema.get_field(field_name);
+
+    if field_option.is_err(){
+        bail!(format!("field {field_name} not found! "));
+    }
+
+    let field = field_option.unwrap();
+
+    // no PrefixQuery in tantivy, the prefix is escaped into a regex
+    let mut pattern = String::with_capacity(prefix.len() + 2);
+    for c in prefix.chars() {
+        if "\\.+*?()|[]{}^$#&-~".contains(c) {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push_str(".*");
+
+    let tq = TQuery{ query: Box::new(RegexQuery::from_pattern(&pattern, field)?), doc_id_filter: None };
+
+    return Ok(Box::new(tq));
+}
+
+/// Matches the terms matching the whole regex pattern, e.g. "colou?r".
+pub fn regex_query(searcher: &mut Searcher, field_name: &String, pattern: &String) -> Result<Box<TQuery>, Box<dyn Error>> {
+    let field_option = searcher.schema.get_field(field_name);
+
+    if field_option.is_err(){
+        bail!(format!("field {field_name} not found! "));
+    }
+
+    let field = field_option.unwrap();
+
+    let tq = TQuery{ query: Box::new(RegexQuery::from_pattern(pattern, field)?), doc_id_filter: None };
+
+    return Ok(Box::new(tq));
+}
+
+/// Range query on a date_field, the bound values are RFC 3339 strings or epoch millis.
+/// The indexed terms of dates are truncated to seconds, so bounds with a fraction of second are rejected.
+pub fn range_query_date(searcher: &mut Searcher, field_name: &String, from_value: &StringBound, to_value: &StringBound) -> Result<Box<TQuery>, Box<dyn Error>> {
+    let field_option = searcher.schema.get_field(field_name);
+
+    if field_option.is_err(){
+        bail!(format!("field {field_name} not found! "));
+    }
+
+    let left: Bound<DateTime> = match from_value.bound{
+        RangeBound::Included => Bound::Included(parse_date_bound(&from_value.value)?),
+        RangeBound::Excluded => Bound::Excluded(parse_date_bound(&from_value.value)?),
+        _ => Bound::Unbounded,
+    };
+
+    let right: Bound<DateTime> = match to_value.bound{
+        RangeBound::Included => Bound::Included(parse_date_bound(&to_value.value)?),
+        RangeBound::Excluded => Bound::Excluded(parse_date_bound(&to_value.value)?),
+        _ => Bound::Unbounded,
+    };
+
+    let tq = TQuery{ query: Box::new(
+        RangeQuery::new_date_bounds(field_name.clone(), left, right)
+     ), doc_id_filter: None };
+
+    return Ok(Box::new(tq));
+}
+
+pub fn boolean_query(queries: & TQueryOccurVec ) -> Result<Box<TQuery>, Box<dyn Error>> {
+    let mut queries_with_occur: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+    let mut doc_id_filter: Option<Arc<RoaringTreemap>> = None;
+
+    for query in & queries.occurs {
+        match query.occur{
+            TOccur::Must => queries_with_occur.push((Occur::Must, query.query.box_clone())),
+            TOccur::MustNot => queries_with_occur.push((Occur::MustNot, query.query.box_clone())),
+            TOccur::Should => queries_with_occur.push((Occur::Should, query.query.box_clone())),
+            _ => log::warn!("Not supported occur!"),
+        }
+
+        // the doc id filters of Must clauses restrict the whole boolean query,
+        // a filter on a Should or MustNot clause can not be applied in the collectors
+        if let Some(filter) = &query.doc_id_filter {
+            match query.occur {
+                TOccur::Must => {
+                    doc_id_filter = match doc_id_filter {
+                        Some(current) => Some(Arc::new(current.as_ref() & filter.as_ref())),
+                        None => Some(filter.clone()),
+                    };
+                }
+                _ => bail!("doc_id_set_query is only supported in Must clauses"),
+            }
+        }
+    }
+
+    let tq = TQuery{ query: Box::new(BooleanQuery::new(queries_with_occur)), doc_id_filter };
+
+    return Ok(Box::new(tq));
+}
+
+/// Restricts the query to the docIds of the bitmap, the filter runs in the segment collectors
+/// on the _docId fast field, so topK, counts and bitmaps only see the allowed documents.
+pub fn doc_id_set_query(query: & TQuery, doc_ids: & SearchResultBitmap) -> Box<TQuery> {
+    let doc_id_filter = match &query.doc_id_filter {
+        Some(current) => current.as_ref() & &doc_ids.bitmap,
+        None => doc_ids.bitmap.clone(),
+    };
+
+    return Box::new(TQuery{ query: query.query.box_clone(), doc_id_filter: Some(Arc::new(doc_id_filter)) });
+}
+
+/// Multiplies the score of the query by factor, e.g. to rank a title match above a body match.
+pub fn boost_query(query: & TQuery, factor: f32) -> Box<TQuery> {
+    return Box::new(TQuery{ query: Box::new(BoostQuery::new(query.query.box_clone(), factor)), doc_id_filter: query.doc_id_filter.clone() });
+}
+
+/// Gives every document matched by the query the same score.
+pub fn const_score_query(query: & TQuery, score: f32) -> Box<TQuery> {
+    return Box::new(TQuery{ query: Box::new(ConstScoreQuery::new(query.query.box_clone(), score)), doc_id_filter: query.doc_id_filter.clone() });
+}
+
+/// Matches the documents of any of the queries, scored by the best matching query
+/// plus tie_breaker times the scores of the other matching queries.
+pub fn disjunction_max_query(queries: & TQueryVec, tie_breaker: f32) -> Result<Box<TQuery>, Box<dyn Error>> {
+    let mut disjuncts: Vec<Box<dyn Query>> = Vec::new();
+
+    for query in & queries.queries {
+        // like a Should clause, a doc id filter can not restrict a single disjunct
+        if query.doc_id_filter.is_some() {
+            bail!("doc_id_set_query is not supported in disjunction_max_query");
+        }
+        disjuncts.push(query.query.box_clone());
+    }
+
+    let tq = TQuery{ query: Box::new(DisjunctionMaxQuery::with_tie_breaker(disjuncts, tie_breaker)), doc_id_filter: None };
+
+    return Ok(Box::new(tq));
+}
+
+pub fn query_occurr(occurr: & TOccur, tquery: & mut TQuery) -> Box<TQueryOccur> {
+    let query_occur = TQueryOccur{occur: *occurr, query: tquery.query.box_clone(), doc_id_filter: tquery.doc_id_filter.clone()};
+    return Box::new(query_occur);
+}
+
+pub fn query_occur_vec() -> Box<TQueryOccurVec>{
+    return Box::new(TQueryOccurVec{occurs: Vec::new()});
+}
+
+
+pub fn append_query_occur_to_vec(occurs_vec: & mut TQueryOccurVec, query_occur: & mut TQueryOccur) {
+    let t = TQueryOccur{occur: query_occur.occur, query: query_occur.query.box_clone(), doc_id_filter: query_occur.doc_id_filter.clone()};
+    occurs_vec.occurs.push(t);
+}
+
+
+/// Resolves a FieldOption, unspecified falls back to the default of the FieldType.
+fn option_enabled(option: FieldOption, default_value: bool) -> bool {
+    match option {
+        FieldOption::enabled => true,
+        FieldOption::disabled => false,
+        _ => default_value,
+    }
+}
+
+/// NumericOptions of int/long/float/double/bool fields, stored and indexed by default.
+fn numeric_options(field_mapping: &FieldMapping) -> NumericOptions {
+    let indexed = option_enabled(field_mapping.indexed, true);
+
+    if !field_mapping.tokenizer.is_empty() {
+        log::warn!("tokenizer {} ignored for {} field {}", field_mapping.tokenizer, field_mapping.field_type, field_mapping.field_name);
+    }
+
+    let mut options = NumericOptions::default();
+    if option_enabled(field_mapping.stored, true) {
+        options = options.set_stored();
+    }
+    if indexed {
+        options = options.set_indexed();
+    }
+    if indexed && option_enabled(field_mapping.fieldnorms, true) {
+        options = options.set_fieldnorm();
+    }
+    if option_enabled(field_mapping.fast, false) {
+        options = options.set_fast(fast_cardinality(field_mapping));
+    }
+
+    return options;
+}
+
+/// DateOptions of date fields, stored and indexed by default with a fast field precision of seconds.
+fn date_options(field_mapping: &FieldMapping) -> DateOptions {
+    let indexed = option_enabled(field_mapping.indexed, true);
+
+    let precision = match field_mapping.date_precision {
+        TDatePrecision::milliseconds => DatePrecision::Milliseconds,
+        TDatePrecision::microseconds => DatePrecision::Microseconds,
+        _ => DatePrecision::Seconds,
+    };
+
+    let mut options = DateOptions::default().set_precision(precision);
+    if option_enabled(field_mapping.stored, true) {
+        options = options.set_stored();
+    }
+    if indexed {
+        options = options.set_indexed();
+    }
+    if indexed && option_enabled(field_mapping.fieldnorms, true) {
+        options = options.set_fieldnorm();
+    }
+    if option_enabled(field_mapping.fast, false) {
+        options = options.set_fast(fast_cardinality(field_mapping));
+    }
+
+    return options;
+}
+
+/// BytesOptions of bytes fields, stored and indexed by default.
+fn bytes_options(field_mapping: &FieldMapping) -> BytesOptions {
+    let indexed = option_enabled(field_mapping.indexed, true);
+
+    let mut options = BytesOptions::default();
+    if option_enabled(field_mapping.stored, true) {
+        options = options.set_stored();
+    }
+    if indexed {
+        options = options.set_indexed();
+    }
+    if indexed && option_enabled(field_mapping.fieldnorms, true) {
+        options = options.set_fieldnorms();
+    }
+    if option_enabled(field_mapping.fast, false) {
+        options = options.set_fast();
+    }
+
+    return options;
+}
+
+fn fast_cardinality(field_mapping: &FieldMapping) -> Cardinality {
+    if field_mapping.multi_valued { Cardinality::MultiValues } else { Cardinality::SingleValue }
+}
+
+/// TextOptions of str/text fields, indexed by default with the given tokenizer and record option.
+fn text_options(field_mapping: &FieldMapping, default_stored: bool, default_tokenizer: &str, default_record: IndexRecordOption) -> Result<TextOptions, Box<dyn Error>> {
+    if option_enabled(field_mapping.fast, false) {
+        bail!(format!("fast is not supported for {} field {}", field_mapping.field_type, field_mapping.field_name));
+    }
+
+    let mut options = TextOptions::default();
+    if option_enabled(field_mapping.stored, default_stored) {
+        options = options.set_stored();
+    }
+
+    if let Some(indexing) = text_indexing(field_mapping, default_tokenizer, default_record) {
+        options = options.set_indexing_options(indexing);
+    }
+
+    return Ok(options);
+}
+
+/// JsonObjectOptions of json fields, stored and indexed by default with the raw tokenizer,
+/// so that string values of a path are matched as a whole by term_query_json().
+fn json_options(field_mapping: &FieldMapping) -> Result<JsonObjectOptions, Box<dyn Error>> {
+    if option_enabled(field_mapping.fast, false) {
+        bail!(format!("fast is not supported for {} field {}", field_mapping.field_type, field_mapping.field_name));
+    }
+
+    let mut options = JsonObjectOptions::default();
+    if option_enabled(field_mapping.stored, true) {
+        options = options.set_stored();
+    }
+
+    if let Some(indexing) = text_indexing(field_mapping, "raw", IndexRecordOption::Basic) {
+        options = options.set_indexing_options(indexing);
+    }
+
+    return Ok(options);
+}
+
+/// Indexing options of str/text/json fields, None if the field is not indexed.
+fn text_indexing(field_mapping: &FieldMapping, default_tokenizer: &str, default_record: IndexRecordOption) -> Option<TextFieldIndexing> {
+    if !option_enabled(field_mapping.indexed, true) {
+        return None;
+    }
+
+    let tokenizer = if field_mapping.tokenizer.is_empty() { default_tokenizer } else { field_mapping.tokenizer.as_str() };
+
+    let record_option = match field_mapping.record_option {
+        RecordOption::basic => IndexRecordOption::Basic,
+        RecordOption::freqs => IndexRecordOption::WithFreqs,
+        RecordOption::positions => IndexRecordOption::WithFreqsAndPositions,
+        _ => default_record,
+    };
+
+    let indexing = TextFieldIndexing::default()
+        .set_tokenizer(tokenizer)
+        .set_index_option(record_option)
+        .set_fieldnorms(option_enabled(field_mapping.fieldnorms, true));
+
+    return Some(indexing);
+}
+
+pub fn query_vec() -> Box<TQueryVec>{
+    return Box::new(TQueryVec{queries: Vec::new()});
+}
+
+pub fn append_query_to_vec(queries: & mut TQueryVec, query: & TQuery) {
+    queries.queries.push(query.clone());
+}
+
+pub fn create_searcher(path: &String, field_mappings:Vec<FieldMapping>) -> Result<Box<Searcher>, Box<dyn Error>>{
+    create_searcher_with_param(path, field_mappings, IndexParam{memory_mbytes: 256, analyzers: Vec::new(), synonyms: Vec::new(), synonyms_path: String::new()})
+}
+
+/// Compares the field mappings with the schema of an existing index without opening a writer,
+/// an empty result means create_searcher() will accept the mappings.
+pub fn validate_field_mappings(path: &String, field_mappings:Vec<FieldMapping>) -> Result<Vec<SchemaDiff>, Box<dyn Error>> {
+    let schema = build_schema(&field_mappings)?;
+
+    let mmap_directory = MmapDirectory::open(std::path::Path::new(path))?;
+    if !Index::exists(&mmap_directory)? {
+        bail!(format!("no index found in {path}"));
+    }
+
+    let index = Index::open(mmap_directory)?;
+
+    return Ok(schema_diff(&schema, &index.schema()));
+}
+
+/// Lists the fields of the index, except _docId and _fields, as FieldMappings that recreate the same schema.
+pub fn get_field_mappings(searcher: & mut Searcher) -> Vec<FieldMapping> {
+    let mut field_mappings: Vec<FieldMapping> = Vec::new();
+
+    for (field, field_entry) in searcher.schema.fields() {
+        if field_entry.name() == "_docId" || field_entry.name() == "_fields" {
+            continue;
+        }
+
+        let mut tokenizer = String::new();
+        let mut record_option = RecordOption::unspecified_record_option;
+        let mut date_precision = TDatePrecision::unspecified_precision;
+
+        let indexing = match field_entry.field_type() {
+            tantivy::schema::FieldType::Str(text_options) => text_options.get_indexing_options(),
+            tantivy::schema::FieldType::JsonObject(json_options) => json_options.get_text_indexing_options(),
+            _ => None,
+        };
+        if let Some(indexing) = indexing {
+            tokenizer = indexing.tokenizer().to_string();
+            record_option = match indexing.index_option() {
+                IndexRecordOption::Basic => RecordOption::basic,
+                IndexRecordOption::WithFreqs => RecordOption::freqs,
+                IndexRecordOption::WithFreqsAndPositions => RecordOption::positions,
+            };
+        }
+
+        if let tantivy::schema::FieldType::Date(date_options) = field_entry.field_type() {
+            date_precision = match date_options.get_precision() {
+                DatePrecision::Milliseconds => TDatePrecision::milliseconds,
+                DatePrecision::Microseconds => TDatePrecision::microseconds,
+                _ => TDatePrecision::seconds,
+            };
+        }
+
+        let flag = |enabled: bool| if enabled { FieldOption::enabled } else { FieldOption::disabled };
+
+        field_mappings.push(FieldMapping{
+            field_name: field_entry.name().to_string(),
+            field_type: field_type_of(searcher, field),
+            stored: flag(field_entry.is_stored()),
+            indexed: flag(field_entry.is_indexed()),
+            fast: flag(field_entry.is_fast()),
+            fieldnorms: flag(field_entry.has_fieldnorms()),
+            record_option,
+            tokenizer,
+            search_tokenizer: searcher.search_tokenizers.get(field_entry.name()).cloned().unwrap_or_default(),
+            date_precision,
+            // only the cardinality of fast fields is kept in the schema, other fields accept several values
+            multi_valued: field_entry.field_type().fastfield_cardinality() != Some(Cardinality::SingleValue),
+        });
+    }
+
+    return field_mappings;
+}
+
+/// Copies the index into a new index at target_path whose schema has additional fields, and returns its Searcher.
+///
+/// The schema of a tantivy index is fixed once created, so fields can not be added in place. The field mappings
+/// must keep every existing field with the same options and may add new fields, which are missing in the copied
+/// documents. Documents are copied from the doc store, so the values of unstored fields, e.g. str_fields with the
+/// default options, can not be copied. The reindex fails on such fields, unless skip_unstored_fields accepts
+/// that they are empty in the new index.
+pub fn reindex_with_field_mappings(searcher: & mut Searcher, target_path: &String, field_mappings:Vec<FieldMapping>, param: IndexParam, skip_unstored_fields: bool) -> Result<Box<Searcher>, Box<dyn Error>> {
+    let new_schema = build_schema(&field_mappings)?;
+
+    for diff in schema_diff(&new_schema, &searcher.schema) {
+        match diff.kind {
+            SchemaDiffKind::missing_in_index => log::info!("reindex adds field {}", diff.field_name),
+            _ => bail!(format!("field {} {:?}, reindex can only add fields", diff.field_name, diff.kind)),
+        }
+    }
+
+    for (_, field_entry) in searcher.schema.fields() {
+        if !field_entry.is_stored() {
+            if !skip_unstored_fields {
+                bail!(format!("field {} is not stored and can not be copied by reindex, pass skip_unstored_fields to leave it empty", field_entry.name()));
+            }
+            log::warn!("field {} is not stored, its values are not copied by reindex", field_entry.name());
+        }
+    }
+
+    let target_dir = MmapDirectory::open(std::path::Path::new(target_path));
+    if let Ok(target_dir) = target_dir {
+        if Index::exists(&target_dir)? {
+            bail!(format!("an index already exists in {target_path}"));
+        }
+    }
+
+    let mut target = create_searcher_with_param(target_path, field_mappings, param)?;
+
+    let start = Instant::now();
+    let index_searcher = searcher.index_reader.searcher();
+    let mut num_copied: u64 = 0;
+
+    for (segment_ord, segment_reader) in index_searcher.segment_readers().iter().enumerate() {
+        for doc in segment_reader.doc_ids_alive() {
+            let retrieved_doc = index_searcher.doc(DocAddress::new(segment_ord as u32, doc))?;
+
+            // fields are matched by name, the field ids of the new schema may differ
+            let mut document = Document::default();
+            for field_value in retrieved_doc.field_values() {
+                let field_name = searcher.schema.get_field_name(field_value.field());
+                let field = target.schema.get_field(field_name)?;
+                document.add(FieldValue::new(field, field_value.value().clone()));
+            }
+            add_field_markers(&target.schema, &mut document);
+
+            target.index_writer.add_document(document)?;
+            num_copied += 1;
+        }
+    }
+
+    target.index_writer.commit()?;
+    _ = target.index_reader.reload(); // reload reader after commit
+
+    log::info!("reindex copied {} docs to {} in {} ms", num_copied, target_path, start.elapsed().as_millis());
+
+    return Ok(target);
+}
+
+/// Replaces the synonyms of the searcher, the next search() and term_query() calls use the new synonyms.
+pub fn set_synonyms(searcher: &mut Searcher, synonym_groups: Vec<SynonymGroup>) -> Result<(), Box<dyn Error>> {
+    searcher.synonyms = build_synonyms(&synonym_groups)?;
+
+    return Ok(());
+}
+
+/// Replaces the synonyms of the searcher with the groups of a synonym file.
+pub fn load_synonyms(searcher: &mut Searcher, path: &String) -> Result<(), Box<dyn Error>> {
+    let synonym_groups = read_synonym_file(path)?;
+    searcher.synonyms = build_synonyms(&synonym_groups)?;
+
+    return Ok(());
+}
+
+/// Reads a synonym file: one group per line, terms separated by commas, an optional ^boost after a term,
+/// e.g. "tv, television^0.8, telly^0.5". Empty lines and lines starting with # are skipped.
+fn read_synonym_file(path: &String) -> Result<Vec<SynonymGroup>, Box<dyn Error>> {
+    let content = std::fs::read_to_string(path)?;
+
+    let mut synonym_groups: Vec<SynonymGroup> = Vec::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut terms: Vec<String> = Vec::new();
+        let mut boosts: Vec<f32> = Vec::new();
+        for entry in line.split(',') {
+            let entry = entry.trim();
+            match entry.rsplit_once('^') {
+                Some((term, boost)) => {
+                    terms.push(term.trim().to_string());
+                    boosts.push(boost.trim().parse::<f32>()?);
+                }
+                None => {
+                    terms.push(entry.to_string());
+                    boosts.push(1.0);
+                }
+            }
+        }
+        synonym_groups.push(SynonymGroup{ terms, boosts });
+    }
+
+    return Ok(synonym_groups);
+}
+
+/// Maps every lowercased term of a group to the other terms of the group and their boost.
+fn build_synonyms(synonym_groups: &Vec<SynonymGroup>) -> Result<HashMap<String, Vec<(String, f32)>>, Box<dyn Error>> {
+    let mut synonyms: HashMap<String, Vec<(String, f32)>> = HashMap::new();
+
+    for group in synonym_groups {
+        if !group.boosts.is_empty() && group.boosts.len() != group.terms.len() {
+            bail!(format!("synonym group {:?} has {} boosts for {} terms", group.terms, group.boosts.len(), group.terms.len()));
+        }
+
+        for term in &group.terms {
+            let entry = synonyms.entry(term.to_lowercase()).or_insert_with(Vec::new);
+            for (index, synonym) in group.terms.iter().enumerate() {
+                if synonym != term {
+                    entry.push((synonym.clone(), group.boosts.get(index).copied().unwrap_or(1.0)));
+                }
+            }
+        }
+    }
+
+    return Ok(synonyms);
+}
+
+/// Rewrites the words of a query text that have synonyms into "(word OR synonym^boost ...)".
+/// Only plain words, optionally prefixed by a field name, are expanded. Words of quoted phrases and of
+/// parenthesized groups are kept as is, like operators and words with a boost or a +/- modifier.
+fn expand_query_synonyms(synonyms: &HashMap<String, Vec<(String, f32)>>, query: &str) -> String {
+    if synonyms.is_empty() {
+        return query.to_string();
+    }
+
+    let mut expanded = String::with_capacity(query.len());
+    let mut word = String::new();
+    let mut in_phrase = false;
+    let mut depth: usize = 0; // nesting of parentheses
+
+    for c in query.chars() {
+        if !in_phrase && depth == 0 && !c.is_whitespace() && c != '"' && c != '(' && c != ')' {
+            word.push(c);
+            continue;
+        }
+
+        if !word.is_empty() {
+            expanded.push_str(&expand_word_synonyms(synonyms, &word));
+            word.clear();
+        }
+
+        match c {
+            '"' => in_phrase = !in_phrase,
+            '(' if !in_phrase => depth += 1,
+            ')' if !in_phrase => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        expanded.push(c);
+    }
+
+    if !word.is_empty() {
+        expanded.push_str(&expand_word_synonyms(synonyms, &word));
+    }
+
+    return expanded;
+}
+
+fn expand_word_synonyms(synonyms: &HashMap<String, Vec<(String, f32)>>, word: &str) -> String {
+    let (prefix, term) = match word.rfind(':') {
+        Some(index) => word.split_at(index + 1),
+        None => ("", word),
+    };
+
+    let plain_word = !term.is_empty() && term.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+    if !plain_word || term == "AND" || term == "OR" || term == "NOT" {
+        return word.to_string();
+    }
+
+    match synonyms.get(&term.to_lowercase()) {
+        Some(term_synonyms) => {
+            let mut clauses: Vec<String> = vec![format!("{prefix}{term}")];
+            for (synonym, boost) in term_synonyms {
+                // a synonym of several words is searched as phrase
+                clauses.push(format!("{prefix}\"{synonym}\"^{boost}"));
+            }
+            format!("({})", clauses.join(" OR "))
+        }
+        None => word.to_string(),
+    }
+}
+
+/// Registers an analyzer in the TokenizerManager of the index, segments created afterwards can use it.
+/// Prefer IndexParam.analyzers for the analyzers of the field mappings, so they exist before the first document is indexed.
+pub fn register_analyzer(searcher: &mut Searcher, analyzer_config: &AnalyzerConfig) -> Result<(), Box<dyn Error>> {
+    let analyzer = build_analyzer(analyzer_config)?;
+
+    searcher.index_writer.index().tokenizers().register(&analyzer_config.name, analyzer);
+
+    return Ok(());
+}
+
+/// Registers a tokenizer implemented in C++ under the given name, for indexing and for query parsing.
+pub fn register_cpp_tokenizer(searcher: &mut Searcher, name: &String, tokenizer: SharedPtr<CppTokenizer>) -> Result<(), Box<dyn Error>> {
+    if tokenizer.is_null() {
+        bail!(format!("tokenizer {name} is null"));
+    }
+
+    let analyzer = TextAnalyzer::from(CppCallbackTokenizer{ tokenizer });
+
+    searcher.index_writer.index().tokenizers().register(name, analyzer);
+
+    return Ok(());
+}
+
+// CppTokenizer::tokenize() is const and documented as thread safe in cpp_tokenizer.h,
+// tantivy calls it from the indexing threads and the searching thread.
+unsafe impl Send for CppTokenizer {}
+unsafe impl Sync for CppTokenizer {}
+
+/// tantivy Tokenizer calling back into a C++ tokenizer.
+#[derive(Clone)]
+struct CppCallbackTokenizer {
+    tokenizer: SharedPtr<CppTokenizer>,
+}
+
+impl Tokenizer for CppCallbackTokenizer {
+    fn token_stream<'a>(&self, text: &'a str) -> BoxTokenStream<'a> {
+        let cpp_tokens = match self.tokenizer.as_ref() {
+            Some(tokenizer) => tokenizer.tokenize(text),
+            None => Vec::new(),
+        };
+
+        // the offsets are used to slice the text, e.g. by the SnippetGenerator, so a token with offsets
+        // out of the text or inside a UTF-8 character would panic there and is dropped instead
+        let tokens: Vec<Token> = cpp_tokens.into_iter()
+            .filter(|cpp_token| {
+                let valid = cpp_token.offset_from <= cpp_token.offset_to
+                    && cpp_token.offset_to <= text.len()
+                    && text.is_char_boundary(cpp_token.offset_from)
+                    && text.is_char_boundary(cpp_token.offset_to);
+                if !valid {
+                    log::warn!("token {} dropped, offsets {}..{} are not valid in a text of {} bytes", cpp_token.text, cpp_token.offset_from, cpp_token.offset_to, text.len());
+                }
+                valid
+            })
+            .enumerate()
+            .map(|(position, cpp_token)| Token{
+                offset_from: cpp_token.offset_from,
+                offset_to: cpp_token.offset_to,
+                position,
+                text: cpp_token.text,
+                position_length: 1,
+            })
+            .collect();
+
+        BoxTokenStream::from(VecTokenStream{ tokens, next: 0 })
+    }
+}
+
+/// TokenStream over the tokens returned by a C++ tokenizer.
+struct VecTokenStream {
+    tokens: Vec<Token>,
+    next: usize, // index of the next token, the current token is at next - 1
+}
+
+impl TokenStream for VecTokenStream {
+    fn advance(&mut self) -> bool {
+        if self.next < self.tokens.len() {
+            self.next += 1;
+            return true;
+        }
+        false
+    }
+
+    fn token(&self) -> &Token {
+        &self.tokens[self.next - 1]
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        &mut self.tokens[self.next - 1]
+    }
+}
+
+fn build_analyzer(analyzer_config: &AnalyzerConfig) -> Result<TextAnalyzer, Box<dyn Error>> {
+    let mut analyzer = match analyzer_config.tokenizer {
+        BaseTokenizer::raw_tokenizer => TextAnalyzer::from(RawTokenizer),
+        BaseTokenizer::ngram_tokenizer => {
+            if analyzer_config.ngram_min == 0 || analyzer_config.ngram_min > analyzer_config.ngram_max {
+                bail!(format!("invalid ngram range {}..{} of analyzer {}", analyzer_config.ngram_min, analyzer_config.ngram_max, analyzer_config.name));
+            }
+            TextAnalyzer::from(NgramTokenizer::new(analyzer_config.ngram_min, analyzer_config.ngram_max, analyzer_config.ngram_prefix_only))
+        }
+        _ => TextAnalyzer::from(SimpleTokenizer),
+    };
+
+    if analyzer_config.remove_long > 0 {
+        analyzer = analyzer.filter(RemoveLongFilter::limit(analyzer_config.remove_long));
+    }
+    if analyzer_config.lowercase {
+        analyzer = analyzer.filter(LowerCaser);
+    }
+    if analyzer_config.ascii_folding {
+        analyzer = analyzer.filter(AsciiFoldingFilter);
+    }
+    if !analyzer_config.stop_words.is_empty() {
+        analyzer = analyzer.filter(StopWordFilter::remove(analyzer_config.stop_words.clone()));
+    }
+    if !analyzer_config.stemmer_language.is_empty() {
+        analyzer = analyzer.filter(Stemmer::new(stemmer_language(&analyzer_config.stemmer_language)?));
+    }
+
+    return Ok(analyzer);
+}
+
+fn stemmer_language(language: &str) -> Result<Language, Box<dyn Error>> {
+    let language = match language.to_lowercase().as_str() {
+        "arabic" => Language::Arabic,
+        "danish" => Language::Danish,
+        "dutch" => Language::Dutch,
+        "english" => Language::English,
+        "finnish" => Language::Finnish,
+        "french" => Language::French,
+        "german" => Language::German,
+        "greek" => Language::Greek,
+        "hungarian" => Language::Hungarian,
+        "italian" => Language::Italian,
+        "norwegian" => Language::Norwegian,
+        "portuguese" => Language::Portuguese,
+        "romanian" => Language::Romanian,
+        "russian" => Language::Russian,
+        "spanish" => Language::Spanish,
+        "swedish" => Language::Swedish,
+        "tamil" => Language::Tamil,
+        "turkish" => Language::Turkish,
+        _ => bail!(format!("stemmer language {language} not supported")),
+    };
+
+    return Ok(language);
+}
+
+/// Builds the index schema: the _docId and _fields fields followed by the fields of the mappings.
+fn build_schema(field_mappings: &Vec<FieldMapping>) -> Result<Schema, Box<dyn Error>> {
+    let mut schema_builder = Schema::builder();
+
+    // set the _docId to be INDEXED for query & delete
+    schema_builder.add_i64_field("_docId", NumericOptions::default() | STORED | INDEXED | FAST);
+
+    // names of the fields having a value in the document, for exists_query()
+    schema_builder.add_text_field("_fields", STRING);
+
+    for field_mapping in field_mappings {
+        if field_mapping.field_name == "_docId" || field_mapping.field_name == "_fields" {
+            bail!(format!("field name {} is reserved", field_mapping.field_name));
+        }
+
+        let _ = match field_mapping.field_type{
+            FieldType::int_field  => schema_builder.add_i64_field(&field_mapping.field_name, numeric_options(field_mapping)),
+            FieldType::long_field => schema_builder.add_i64_field(&field_mapping.field_name, numeric_options(field_mapping)),
+            FieldType::float_field => schema_builder.add_f64_field(&field_mapping.field_name, numeric_options(field_mapping)),
+            FieldType::double_field => schema_builder.add_f64_field(&field_mapping.field_name, numeric_options(field_mapping)),
+            FieldType::str_field => schema_builder.add_text_field(&field_mapping.field_name, text_options(field_mapping, false, "raw", IndexRecordOption::Basic)?),
+            FieldType::bool_field => schema_builder.add_bool_field(&field_mapping.field_name, numeric_options(field_mapping)),
+            FieldType::text_field => schema_builder.add_text_field(&field_mapping.field_name, text_options(field_mapping, true, "default", IndexRecordOption::WithFreqsAndPositions)?),
+            FieldType::date_field => schema_builder.add_date_field(&field_mapping.field_name, date_options(field_mapping)),
+            FieldType::ulong_field => schema_builder.add_u64_field(&field_mapping.field_name, numeric_options(field_mapping)),
+            FieldType::bytes_field => schema_builder.add_bytes_field(&field_mapping.field_name, bytes_options(field_mapping)),
+            FieldType::json_field => schema_builder.add_json_field(&field_mapping.field_name, json_options(field_mapping)?),
+
+            _ => schema_builder.add_text_field(&field_mapping.field_name, text_options(field_mapping, false, "raw", IndexRecordOption::Basic)?),
+        };
+    }
+
+    return Ok(schema_builder.build());
+}
+
+/// Field by field differences between the schema built from the field mappings and the schema stored in the index.
+fn schema_diff(expected: &Schema, actual: &Schema) -> Vec<SchemaDiff> {
+    let mut diffs: Vec<SchemaDiff> = Vec::new();
+
+    for (_, expected_entry) in expected.fields() {
+        let expected_json = serde_json::to_string(expected_entry).unwrap_or_default();
+
+        match actual.get_field(expected_entry.name()) {
+            Ok(field) => {
+                let actual_entry = actual.get_field_entry(field);
+                if actual_entry != expected_entry {
+                    diffs.push(SchemaDiff{
+                        field_name: expected_entry.name().to_string(),
+                        kind: SchemaDiffKind::options_mismatch,
+                        expected: expected_json,
+                        actual: serde_json::to_string(actual_entry).unwrap_or_default(),
+                    });
+                }
+            }
+            // indexes created before _fields existed are still valid, exists_query() falls back to a range
+            Err(_) if expected_entry.name() == "_fields" => {}
+            Err(_) => {
+                diffs.push(SchemaDiff{
+                    field_name: expected_entry.name().to_string(),
+                    kind: SchemaDiffKind::missing_in_index,
+                    expected: expected_json,
+                    actual: String::new(),
+                });
+            }
+        }
+    }
+
+    for (_, actual_entry) in actual.fields() {
+        if expected.get_field(actual_entry.name()).is_err() {
+            diffs.push(SchemaDiff{
+                field_name: actual_entry.name().to_string(),
+                kind: SchemaDiffKind::missing_in_mappings,
+                expected: String::new(),
+                actual: serde_json::to_string(actual_entry).unwrap_or_default(),
+            });
+        }
+    }
+
+    return diffs;
+}
+
+pub fn create_searcher_with_param(path: &String, field_mappings:Vec<FieldMapping>, param: IndexParam) -> Result<Box<Searcher>, Box<dyn Error>>
+{
+    std::fs::create_dir_all("logs")?;
+    _ = simple_logging::log_to_file("logs/tantivy_index.log", LevelFilter::Info);
+    log::info!("Rust logging initialized");
+
+    let index_dir = std::path::Path::new(path);
+    let index_path = index_dir;
+
+    std::fs::create_dir_all(index_path)?; // create dir if not exist
+
+    index_path.to_str().expect("index_path should not be empty");
+
+    let mut field_types: HashMap<String, FieldType> = HashMap::new();
+    let mut search_tokenizers: HashMap<String, String> = HashMap::new();
+    for field_mapping in &field_mappings {
+        field_types.insert(field_mapping.field_name.clone(), field_mapping.field_type);
+        if !field_mapping.search_tokenizer.is_empty() {
+            search_tokenizers.insert(field_mapping.field_name.clone(), field_mapping.search_tokenizer.clone());
+        }
+    }
+
+    let schema = build_schema(&field_mappings)?;
+
+    //let index = Index::create_in_dir(&index_path, schema.clone())?;
+    let mmap_directory = MmapDirectory::open(index_path)?;
+    let index = if Index::exists(&mmap_directory)? {
+        let index = Index::open(mmap_directory)?;
+
+        // empty field_mappings open the index with its stored schema, see get_field_mappings()
+        if !field_mappings.is_empty() {
+            let diffs = schema_diff(&schema, &index.schema());
+            if !diffs.is_empty() {
+                let diff_messages: Vec<String> = diffs.iter()
+                    .map(|diff| format!("{} {:?} expected:{} actual:{}", diff.field_name, diff.kind, diff.expected, diff.actual))
+                    .collect();
+                bail!(format!("field mappings don't match the schema of index {path}, use reindex_with_field_mappings() to add fields: {}", diff_messages.join("; ")));
+            }
+        }
+        index
+    } else {
+        if field_mappings.is_empty() {
+            bail!(format!("no index found in {path} to open with its stored schema, field mappings are required to create an index"));
+        }
+        Index::create(mmap_directory, schema, IndexSettings::default())?
+    };
+
+    let schema = index.schema();
+
+    for analyzer_config in &param.analyzers {
+        let analyzer = build_analyzer(analyzer_config)?;
+        index.tokenizers().register(&analyzer_config.name, analyzer);
+    }
+
+    let index_writer = index.writer(param.memory_mbytes * 1000_000)?;
+
+    let mut merge_policy = LogMergePolicy::default();
+    merge_policy.set_max_docs_before_merge(320000);
+    merge_policy.set_min_num_segments(3);
+    merge_policy.set_min_layer_size(60000);
 
     index_writer.set_merge_policy(Box::new(merge_policy));
 
-    let reader = index_writer.index()
-        .reader_builder()
-        .reload_policy(ReloadPolicy::OnCommit)
-        .try_into()?;
-    let searcher = Searcher{_index_path:path.to_string(), schema: schema, index_writer: index_writer, index_reader: reader};
+    let reader = index_writer.index()
+        .reader_builder()
+        .reload_policy(ReloadPolicy::OnCommit)
+        .try_into()?;
+    let mut synonym_groups = param.synonyms;
+    if !param.synonyms_path.is_empty() {
+        synonym_groups.extend(read_synonym_file(&param.synonyms_path)?);
+    }
+    let synonyms = build_synonyms(&synonym_groups)?;
+
+    let searcher = Searcher{_index_path:path.to_string(), schema: schema, field_types: field_types, search_tokenizers: search_tokenizers, synonyms: synonyms, index_writer: index_writer, index_reader: reader};
+
+    return Ok(Box::new(searcher));
+}
+
+
+pub fn add_document(searcher: & mut Searcher, docs:Vec<IdDocument>, commit: bool) -> Result<(), Box<dyn Error>>{
+    let index_writer = &mut searcher.index_writer;
+
+    for doc in docs{
+        let document = build_document(&searcher.schema, doc)?;
+
+        index_writer.add_document(document)?;
+    }
+     
+    if commit {
+        index_writer.commit()?;
+        _ = searcher.index_reader.reload(); // reload reader after commit
+    }
+    
+    return Ok(());
+}
+
+/// Replaces the documents with the same _docId, or adds them if they don't exist yet, and returns the opstamp.
+///
+/// The delete of the old _docId term and the add of the new document are sent as one batch of writer
+/// operations, so a commit always contains both or neither: readers keep seeing the old document until
+/// the commit that contains the upsert, then only the new one, never both or none.
+pub fn upsert_document(searcher: & mut Searcher, docs:Vec<IdDocument>, commit: bool) -> Result<u64, Box<dyn Error>>{
+    let index_writer = &mut searcher.index_writer;
+
+    let id_field = searcher.schema.get_field("_docId").unwrap();
+
+    let mut operations: Vec<UserOperation> = Vec::new();
+    for doc in docs{
+        operations.push(UserOperation::Delete(Term::from_field_i64(id_field, doc.docId)));
+        operations.push(UserOperation::Add(build_document(&searcher.schema, doc)?));
+    }
+
+    let opstamp = index_writer.run(operations)?;
+
+    if commit {
+        index_writer.commit()?;
+        _ = searcher.index_reader.reload(); // reload reader after commit
+    }
+
+    return Ok(opstamp);
+}
+
+/// Parses a date given as RFC 3339 string, e.g. "2022-11-08T10:15:00+01:00", or as epoch millis.
+fn parse_date(value: &str) -> Result<DateTime, Box<dyn Error>> {
+    if let Ok(epoch_millis) = value.parse::<i64>() {
+        return Ok(DateTime::from_timestamp_millis(epoch_millis));
+    }
+
+    match OffsetDateTime::parse(value, &Rfc3339) {
+        Ok(date_time) => Ok(DateTime::from_utc(date_time)),
+        Err(err) => bail!(format!("invalid date {value}: {err}")),
+    }
+}
+
+/// Parses a bound of range_query_date(), a sub-second bound would be truncated like the indexed terms
+/// and match the wrong documents, e.g. an excluded bound at 10:00:00.500 would drop a date at 10:00:00.700.
+fn parse_date_bound(value: &str) -> Result<DateTime, Box<dyn Error>> {
+    let date_time = parse_date(value)?;
+
+    if date_time.into_utc().nanosecond() != 0 {
+        bail!(format!("date bound {value} has a fraction of second, date terms are indexed in seconds"));
+    }
+
+    return Ok(date_time);
+}
+
+/// Converts an IdDocument to a tantivy Document, parsing every field value according to its FieldType.
+/// Fast fields may only appear more than once when multi valued, the cardinality of a fast field is kept
+/// in the schema while other fields can not be marked single valued. Every value is indexed and stored.
+fn build_document(schema: &Schema, doc: IdDocument) -> Result<Document, Box<dyn Error>>{
+    let id_field = schema.get_field("_docId").unwrap();
+
+    let mut document = Document::default();
+
+    document.add_i64(id_field, doc.docId);
+
+    let mut seen_fields: HashSet<Field> = HashSet::new();
+
+    for doc_field in doc.fieldValues{
+        let field_option = schema.get_field(&doc_field.field_name);
+
+        match field_option {
+            Ok(field) => {
+                let single_valued = schema.get_field_entry(field).field_type().fastfield_cardinality() == Some(Cardinality::SingleValue);
+                if !seen_fields.insert(field) && single_valued {
+                    bail!(format!("field {} of doc {} has several values but is not multi_valued! ", doc_field.field_name, doc.docId));
+                }
+
+                let field_value = doc_field.field_value;
+                let _ = match doc_field.field_type {
+                    FieldType::int_field  => document.add_i64(field, field_value.as_str().parse::<i64>()?),
+                    FieldType::long_field => document.add_i64(field, field_value.as_str().parse::<i64>()?),
+                    FieldType::float_field => document.add_f64(field, field_value.as_str().parse::<f64>()?),
+                    FieldType::double_field => document.add_f64(field, field_value.as_str().parse::<f64>()?),
+                    FieldType::str_field => document.add_text(field, field_value),
+                    //field_value should be "true", "false"
+                    FieldType::bool_field => document.add_bool(field, field_value.to_lowercase().as_str().parse::<bool>()?),
+                    FieldType::text_field => document.add_text(field, field_value),
+                    FieldType::date_field => document.add_date(field, parse_date(field_value.as_str())?),
+                    FieldType::ulong_field => document.add_u64(field, field_value.as_str().parse::<u64>()?),
+                    FieldType::bytes_field => document.add_bytes(field, base64::decode(field_value.as_str())?),
+                    FieldType::json_field => document.add_json_object(field, serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(field_value.as_str())?),
+
+                    _ => log::warn!("Not supported FieldType {}", doc_field.field_type.to_string()),
+                };
+            }
+            Err(_) => {
+                bail!(format!("field {} not found! ", doc_field.field_name));
+            }
+        }
+    }
+    add_field_markers(schema, &mut document);
+
+    return Ok(document);
+}
+
+/// Adds the names of the fields having a value to the _fields field, if the schema has one.
+fn add_field_markers(schema: &Schema, document: &mut Document) {
+    let fields_field = match schema.get_field("_fields") {
+        Ok(field) => field,
+        Err(_) => return,
+    };
+
+    let mut field_names: Vec<String> = Vec::new();
+    for field_value in document.field_values() {
+        let field_name = schema.get_field_name(field_value.field());
+        if field_name != "_docId" && !field_names.iter().any(|name| name == field_name) {
+            field_names.push(field_name.to_string());
+        }
+    }
+
+    for field_name in field_names {
+        document.add_text(fields_field, field_name);
+    }
+}
+
+pub fn delete_document(searcher: &mut Searcher, doc_ids:Vec<i64>, commit: bool) -> Result<(), Box<dyn Error>> {
+
+    log::info!("delete doc_ids:{:?}", doc_ids);
+
+    let id_field = searcher.schema.get_field("_docId").unwrap();
+
+    let index_writer = &mut searcher.index_writer;
+
+    for doc_id in doc_ids {
+        let term = Term::from_field_i64(id_field, doc_id);
+        index_writer.delete_term(term);
+    }
+    
+    if commit {
+        index_writer.commit()?;
+        _ = searcher.index_reader.reload(); // reload reader after commit
+    }
+
+    return Ok(());
+}
+
+/// Deletes every document matching the query and returns the opstamp of the delete operation.
+/// The matches are resolved by the writer when the delete is applied, documents added after this call are not affected.
+pub fn delete_by_query(searcher: &mut Searcher, query: & TQuery, commit: bool) -> Result<u64, Box<dyn Error>> {
+    log::info!("delete by query:{:?}", query.query);
+
+    if query.doc_id_filter.is_some() {
+        bail!("doc_id_set_query is not supported by delete_by_query");
+    }
+
+    let index_writer = &mut searcher.index_writer;
+
+    let opstamp = index_writer.delete_query(query.query.box_clone())?;
+
+    if commit {
+        index_writer.commit()?;
+        _ = searcher.index_reader.reload(); // reload reader after commit
+    }
+
+    return Ok(opstamp);
+}
+
+pub fn commit_index(searcher: &mut Searcher)  -> Result<(), Box<dyn Error>> {
+    let index_writer = &mut searcher.index_writer;
+
+    index_writer.commit()?;
+    _ = searcher.index_reader.reload(); // reload reader after commit
+
+    return Ok(());
+}
+
+pub fn search(searcher: & mut Searcher, query: & String, search_fields: & Vec<String>, search_param: & SearchParam) -> Result<Vec<IdDocument>, Box<dyn Error>> {
+
+    let index_searcher = searcher.index_reader.searcher();
+    log::info!("query:{}", query);
+
+    let doc_id_field = searcher.schema.get_field("_docId").unwrap();
+
+    let mut fields: Vec<Field> = Vec::new();
+
+    for search_field in search_fields{
+        let field_option = searcher.schema.get_field(search_field.as_str());
+
+        match field_option {
+            Ok(field) => {
+                fields.push(field);
+            }
+            Err(err) => {
+                bail!(format!("field {search_field} not found! "));
+            }
+        }
+    }
+
+    let return_fields = resolve_return_fields(searcher, search_param)?;
+
+    let query_parser = query_parser(searcher, fields)?;
+    let query = query_parser.parse_query(expand_query_synonyms(&searcher.synonyms, query).as_str())?;
+
+    let top_docs = top_docs(&index_searcher, query.as_ref(), &None, search_param)?;
+    let snippet_generators = snippet_generators(searcher, &index_searcher, query.as_ref(), search_param)?;
+
+    let mut id_documents: Vec<IdDocument> =  Vec::new();
+
+    for (_score, doc_address, sort_values) in top_docs {
+        let retrieved_doc = index_searcher.doc(doc_address)?;
+        println!("score:{} {}", _score, searcher.schema.to_json(&retrieved_doc));
+
+        let doc_id = retrieved_doc.get_first(doc_id_field) ;
+    
+        if doc_id.is_some() {
+            let current_id = doc_id.expect("error getting doc_id").as_i64().expect("as_i64");
+            let field_values = if search_param.with_field_values {
+                stored_field_values(searcher, &retrieved_doc, &return_fields)
+            } else {
+                Vec::new()
+            };
+            let snippets = snippets(&snippet_generators, &retrieved_doc, &search_param.snippet);
+            let document = IdDocument{docId:current_id, fieldValues: field_values, score: _score, sortValues: sort_values, snippets: snippets };
+            id_documents.push(document);
+        }
+    }
+
+    return Ok(id_documents);
+}
+
+/// QueryParser of search(), the query text of a field is analyzed by its search_tokenizer if it has one.
+///
+/// The QueryParser takes the analyzer of a field from the tokenizer of its schema entry, so it parses with a
+/// copy of the schema where the search tokenizers replace the indexing tokenizers. The fields are added in
+/// the same order, so their ids are the ones of the index schema.
+fn query_parser(searcher: &Searcher, fields: Vec<Field>) -> Result<QueryParser, Box<dyn Error>> {
+    if searcher.search_tokenizers.is_empty() {
+        return Ok(QueryParser::for_index(searcher.index_writer.index(), fields));
+    }
+
+    let tokenizer_manager = searcher.index_writer.index().tokenizers().clone();
+
+    let mut schema_builder = Schema::builder();
+    for (_, field_entry) in searcher.schema.fields() {
+        let search_tokenizer = searcher.search_tokenizers.get(field_entry.name());
+
+        let search_entry = match (field_entry.field_type(), search_tokenizer) {
+            (tantivy::schema::FieldType::Str(text_options), Some(search_tokenizer)) => {
+                match text_options.get_indexing_options() {
+                    Some(indexing) => {
+                        if tokenizer_manager.get(search_tokenizer).is_none() {
+                            bail!(format!("search tokenizer {search_tokenizer} of field {} not found! ", field_entry.name()));
+                        }
+                        let search_options = text_options.clone().set_indexing_options(indexing.clone().set_tokenizer(search_tokenizer));
+                        FieldEntry::new_text(field_entry.name().to_string(), search_options)
+                    }
+                    None => field_entry.clone(),
+                }
+            }
+            _ => field_entry.clone(),
+        };
+        schema_builder.add_field(search_entry);
+    }
+
+    return Ok(QueryParser::new(schema_builder.build(), fields, tokenizer_manager));
+}
+
+pub fn search_by_query(searcher: & mut Searcher, query: & TQuery, search_param: & SearchParam) -> Result<Vec<IdDocument>, Box<dyn Error>> {
+    let index_searcher = searcher.index_reader.searcher();
+    //println!("query:{}", query);
+
+    let doc_id_field = searcher.schema.get_field("_docId").unwrap();
+    let return_fields = resolve_return_fields(searcher, search_param)?;
+    let top_docs = top_docs(&index_searcher, query.query.as_ref(), &query.doc_id_filter, search_param)?;
+    let snippet_generators = snippet_generators(searcher, &index_searcher, query.query.as_ref(), search_param)?;
+
+    let mut id_documents: Vec<IdDocument> =  Vec::new();
+
+    for (_score, doc_address, sort_values) in top_docs {
+        let retrieved_doc = index_searcher.doc(doc_address)?;
+        log::info!("score:{} {}", _score, searcher.schema.to_json(&retrieved_doc));
+
+        let doc_id = retrieved_doc.get_first(doc_id_field) ;
+    
+        if doc_id.is_some() {
+            let current_id = doc_id.expect("error getting doc_id").as_i64().expect("as_i64");
+            let field_values = if search_param.with_field_values {
+                stored_field_values(searcher, &retrieved_doc, &return_fields)
+            } else {
+                Vec::new()
+            };
+            let snippets = snippets(&snippet_generators, &retrieved_doc, &search_param.snippet);
+            let document = IdDocument{docId:current_id, fieldValues: field_values, score: _score, sortValues: sort_values, snippets: snippets };
+            id_documents.push(document);
+        }
+    }
+
+    return Ok(id_documents);
+}
+
+/// Best hits of the query for the page described by topK, offset, search_after and sort_by,
+/// with the sort values of each hit when sorting by fast fields.
+fn top_docs(index_searcher: &tantivy::Searcher, query: &dyn Query, doc_id_filter: &Option<Arc<RoaringTreemap>>, search_param: &SearchParam) -> Result<Vec<(Score, DocAddress, Vec<String>)>, Box<dyn Error>> {
+    if !search_param.sort_by.is_empty() {
+        if search_param.search_after.enabled {
+            bail!("search_after is not supported together with sort_by, use offset instead");
+        }
+        return sorted_top_docs(index_searcher, query, doc_id_filter, search_param);
+    }
+
+    // TopDocs prunes the hits that can't make the page, its ties are broken by DocAddress though,
+    // see SearchAfter for a first page ranked like the pages after a cursor
+    if !search_param.search_after.enabled {
+        let hits = search_filtered(index_searcher, query, doc_id_filter, TopDocs::with_limit(search_param.topK).and_offset(search_param.offset))?;
+        return Ok(hits.into_iter().map(|(score, doc_address)| (score, doc_address, Vec::new())).collect());
+    }
+
+    let collector = SearchAfterCollector{
+        limit: search_param.offset + search_param.topK,
+        after_score: search_param.search_after.score,
+        after_doc_id: search_param.search_after.docId,
+    };
+    let hits = search_filtered(index_searcher, query, doc_id_filter, collector)?;
+
+    return Ok(hits.into_iter().skip(search_param.offset).map(|(score, doc_address)| (score, doc_address, Vec::new())).collect());
+}
+
+/// Ranks the hits by the fast fields of SearchParam.sort_by and then by _docId asc.
+///
+/// Every sort value is mapped to an order preserving u64 (inverted for Asc), so the
+/// sort key of a hit is a Vec<u64> and TopDocs keeps the hits with the highest keys.
+fn sorted_top_docs(index_searcher: &tantivy::Searcher, query: &dyn Query, doc_id_filter: &Option<Arc<RoaringTreemap>>, search_param: &SearchParam) -> Result<Vec<(Score, DocAddress, Vec<String>)>, Box<dyn Error>> {
+    let schema = index_searcher.schema();
+
+    let mut sort_fields: Vec<(String, Type, SortOrder)> = Vec::new();
+    for sort_field in &search_param.sort_by {
+        let field_name = &sort_field.field_name;
+        let field = match schema.get_field(field_name) {
+            Ok(field) => field,
+            Err(_) => bail!(format!("field {field_name} not found! ")),
+        };
+
+        let field_entry = schema.get_field_entry(field);
+        if !field_entry.is_fast() {
+            bail!(format!("field {field_name} is not a fast field! "));
+        }
+
+        if field_entry.field_type().fastfield_cardinality() == Some(Cardinality::MultiValues) {
+            bail!(format!("field {field_name} is multi_valued and can not be sorted on! "));
+        }
+
+        let value_type = field_entry.field_type().value_type();
+        match value_type {
+            Type::I64 | Type::U64 | Type::F64 | Type::Bool => sort_fields.push((field_name.clone(), value_type, sort_field.order)),
+            _ => bail!(format!("field {field_name} of type {value_type:?} can not be sorted on! ")),
+        }
+    }
+
+    let key_fields = sort_fields.clone();
+    let collector = TopDocs::with_limit(search_param.topK)
+        .and_offset(search_param.offset)
+        .tweak_score(move |segment_reader: &SegmentReader| {
+            let fast_fields = segment_reader.fast_fields();
+            let readers: Vec<(Box<dyn Fn(u32) -> u64 + Send + Sync>, SortOrder)> = key_fields.iter()
+                .map(|(field_name, value_type, order)| (sort_value_reader(fast_fields, field_name, *value_type), *order))
+                .collect();
+            let doc_id_reader = fast_fields.i64("_docId").unwrap();
+
+            move |doc: u32, score: Score| {
+                let mut key: Vec<u64> = Vec::with_capacity(readers.len() + 1);
+                for (reader, order) in &readers {
+                    match order {
+                        SortOrder::Asc => key.push(!reader(doc)),
+                        _ => key.push(reader(doc)),
+                    }
+                }
+                key.push(!i64_to_sortable(doc_id_reader.get_val(doc)));
+                (key, score)
+            }
+        });
+
+    let hits = search_filtered(index_searcher, query, doc_id_filter, collector)?;
+
+    let mut top_docs: Vec<(Score, DocAddress, Vec<String>)> = Vec::new();
+    for ((key, score), doc_address) in hits {
+        let sort_values = sort_fields.iter().zip(key.iter())
+            .map(|((_, value_type, order), sortable)| {
+                let sortable = match order {
+                    SortOrder::Asc => !*sortable,
+                    _ => *sortable,
+                };
+                sortable_to_string(sortable, *value_type)
+            })
+            .collect();
+        top_docs.push((score, doc_address, sort_values));
+    }
+
+    return Ok(top_docs);
+}
+
+/// Reads a fast field of a segment as order preserving u64, the field type was checked by the caller.
+fn sort_value_reader(fast_fields: &tantivy::fastfield::FastFieldReaders, field_name: &str, value_type: Type) -> Box<dyn Fn(u32) -> u64 + Send + Sync> {
+    match value_type {
+        Type::I64 => {
+            let reader = fast_fields.i64(field_name).unwrap();
+            Box::new(move |doc| i64_to_sortable(reader.get_val(doc)))
+        }
+        Type::F64 => {
+            let reader = fast_fields.f64(field_name).unwrap();
+            Box::new(move |doc| f64_to_sortable(reader.get_val(doc)))
+        }
+        Type::Bool => {
+            let reader = fast_fields.bool(field_name).unwrap();
+            Box::new(move |doc| reader.get_val(doc) as u64)
+        }
+        _ => {
+            let reader = fast_fields.u64(field_name).unwrap();
+            Box::new(move |doc| reader.get_val(doc))
+        }
+    }
+}
+
+/// Maps an i64 to an order preserving u64, flipping the sign bit keeps the mapping one to one.
+/// Sort keys and the members of the docId bitmaps use it, so -5 and 5 are distinct members and min/max still hold.
+fn i64_to_sortable(value: i64) -> u64 {
+    (value as u64) ^ (1u64 << 63)
+}
+
+fn sortable_to_i64(sortable: u64) -> i64 {
+    (sortable ^ (1u64 << 63)) as i64
+}
+
+/// Maps an f64 to an order preserving u64, -0.0 ranks just before 0.0 and NaNs with the sign bit
+/// set before -infinity, the other NaNs after +infinity.
+fn f64_to_sortable(value: f64) -> u64 {
+    let bits = value.to_bits();
+    if bits & (1u64 << 63) != 0 { !bits } else { bits | (1u64 << 63) }
+}
+
+fn sortable_to_f64(sortable: u64) -> f64 {
+    let bits = if sortable & (1u64 << 63) != 0 { sortable & !(1u64 << 63) } else { !sortable };
+    f64::from_bits(bits)
+}
+
+fn sortable_to_string(sortable: u64, value_type: Type) -> String {
+    match value_type {
+        Type::I64 => sortable_to_i64(sortable).to_string(),
+        Type::F64 => sortable_to_f64(sortable).to_string(),
+        Type::Bool => (sortable != 0).to_string(),
+        _ => sortable.to_string(),
+    }
+}
+
+/// Runs the collector, restricted to the docIds of the filter if there is one.
+fn search_filtered<TCollector: Collector>(index_searcher: &tantivy::Searcher, query: &dyn Query, doc_id_filter: &Option<Arc<RoaringTreemap>>, collector: TCollector) -> tantivy::Result<TCollector::Fruit> {
+    match doc_id_filter {
+        Some(filter) => index_searcher.search(query, &DocIdFilterCollector{ filter: filter.clone(), collector }),
+        None => index_searcher.search(query, &collector),
+    }
+}
+
+/// Passes on to the wrapped collector only the documents whose _docId is in the filter.
+struct DocIdFilterCollector<TCollector> {
+    filter: Arc<RoaringTreemap>,
+    collector: TCollector,
+}
+
+impl<TCollector: Collector> Collector for DocIdFilterCollector<TCollector> {
+    type Fruit = TCollector::Fruit;
+
+    type Child = DocIdFilterSegmentCollector<TCollector::Child>;
+
+    fn for_segment(&self, segment_local_id: u32, segment_reader: &SegmentReader) -> tantivy::Result<DocIdFilterSegmentCollector<TCollector::Child>> {
+        let doc_id_reader = segment_reader.fast_fields().i64("_docId")?;
+        let segment_collector = self.collector.for_segment(segment_local_id, segment_reader)?;
+
+        Ok(DocIdFilterSegmentCollector {
+            filter: self.filter.clone(),
+            doc_id_reader,
+            segment_collector,
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        self.collector.requires_scoring()
+    }
+
+    fn merge_fruits(&self, segment_fruits: Vec<<TCollector::Child as SegmentCollector>::Fruit>) -> tantivy::Result<TCollector::Fruit> {
+        self.collector.merge_fruits(segment_fruits)
+    }
+}
+
+struct DocIdFilterSegmentCollector<TSegmentCollector> {
+    filter: Arc<RoaringTreemap>,
+    doc_id_reader: Arc<dyn Column<i64>>,
+    segment_collector: TSegmentCollector,
+}
+
+impl<TSegmentCollector: SegmentCollector> SegmentCollector for DocIdFilterSegmentCollector<TSegmentCollector> {
+    type Fruit = TSegmentCollector::Fruit;
+
+    fn collect(&mut self, doc: u32, score: Score) {
+        let doc_id = self.doc_id_reader.get_val(doc);
+        if self.filter.contains(i64_to_sortable(doc_id)) {
+            self.segment_collector.collect(doc, score);
+        }
+    }
 
-    return Ok(Box::new(searcher));
+    fn harvest(self) -> TSegmentCollector::Fruit {
+        self.segment_collector.harvest()
+    }
 }
 
+/// Ranks hits by score desc, then by _docId asc so that hits with equal scores have a stable order across pages.
+fn compare_hits(left: &(Score, i64), right: &(Score, i64)) -> std::cmp::Ordering {
+    right.0.partial_cmp(&left.0).unwrap_or(std::cmp::Ordering::Equal).then(left.1.cmp(&right.1))
+}
 
-pub fn add_document(searcher: & mut Searcher, docs:Vec<IdDocument>, commit: bool) -> Result<(), Box<dyn Error>>{
-    let index_writer = &mut searcher.index_writer;
-    
-    let id_field = searcher.schema.get_field("_docId").unwrap();
+/// Collects the `limit` best hits ranked strictly after the (score, _docId) cursor.
+struct SearchAfterCollector {
+    limit: usize,
+    after_score: Score,
+    after_doc_id: i64,
+}
 
-    for doc in docs{
-        let mut document = Document::default();
-
-        document.add_i64(id_field, doc.docId);
-
-        for doc_field in doc.fieldValues{
-            let field_option = searcher.schema.get_field(&doc_field.field_name);
-
-            match field_option {
-                Ok(field) => {
-                    let field_value = doc_field.field_value;
-                    let _ = match doc_field.field_type {
-                        FieldType::int_field  => document.add_i64(field, field_value.as_str().parse::<i64>()?),
-                        FieldType::long_field => document.add_i64(field, field_value.as_str().parse::<i64>()?),
-                        FieldType::float_field => document.add_f64(field, field_value.as_str().parse::<f64>()?),
-                        FieldType::double_field => document.add_f64(field, field_value.as_str().parse::<f64>()?),
-                        FieldType::str_field => document.add_text(field, field_value),
-                        //field_value should be "true", "false"
-                        FieldType::bool_field => document.add_bool(field, field_value.to_lowercase().as_str().parse::<bool>()?),
-                        FieldType::text_field => document.add_text(field, field_value),
-
-                        _ => log::warn!("Not supported FieldType {}", doc_field.field_type.to_string()),
-                    };
-                }
-                Err(err) => {
-                    bail!(format!("field {} not found! ", doc_field.field_name));
-                }
-            }
-        }
-        
-        index_writer.add_document(document)?;
+impl Collector for SearchAfterCollector {
+    type Fruit = Vec<(Score, DocAddress)>;
+
+    type Child = SearchAfterSegmentCollector;
+
+    fn for_segment(&self, segment_local_id: u32, segment_reader: &SegmentReader) -> tantivy::Result<SearchAfterSegmentCollector> {
+        let doc_id_reader = segment_reader.fast_fields().i64("_docId")?;
+
+        Ok(SearchAfterSegmentCollector {
+            segment_ord: segment_local_id,
+            doc_id_reader,
+            limit: self.limit,
+            after: (self.after_score, self.after_doc_id),
+            hits: Vec::new(),
+        })
     }
-     
-    if commit {
-        index_writer.commit()?;
-        _ = searcher.index_reader.reload(); // reload reader after commit
+
+    fn requires_scoring(&self) -> bool {
+        true
+    }
+
+    fn merge_fruits(&self, segment_fruits: Vec<Vec<(Score, i64, DocAddress)>>) -> tantivy::Result<Vec<(Score, DocAddress)>> {
+        let mut hits: Vec<(Score, i64, DocAddress)> = segment_fruits.into_iter().flatten().collect();
+        hits.sort_by(|left, right| compare_hits(&(left.0, left.1), &(right.0, right.1)));
+        hits.truncate(self.limit);
+
+        Ok(hits.into_iter().map(|(score, _, doc_address)| (score, doc_address)).collect())
     }
-    
-    return Ok(());
 }
 
-pub fn delete_document(searcher: &mut Searcher, doc_ids:Vec<i64>, commit: bool) -> Result<(), Box<dyn Error>> {
+struct SearchAfterSegmentCollector {
+    segment_ord: u32,
+    doc_id_reader: Arc<dyn Column<i64>>,
+    limit: usize,
+    after: (Score, i64),
+    hits: Vec<(Score, i64, DocAddress)>,
+}
 
-    log::info!("delete doc_ids:{:?}", doc_ids);
+impl SearchAfterSegmentCollector {
+    // keep the best `limit` hits only, sorting once the buffer doubled keeps the cost amortized
+    fn truncate_hits(&mut self) {
+        self.hits.sort_by(|left, right| compare_hits(&(left.0, left.1), &(right.0, right.1)));
+        self.hits.truncate(self.limit);
+    }
+}
 
-    let id_field = searcher.schema.get_field("_docId").unwrap();
+impl SegmentCollector for SearchAfterSegmentCollector {
+    type Fruit = Vec<(Score, i64, DocAddress)>;
 
-    let index_writer = &mut searcher.index_writer;
+    fn collect(&mut self, doc: u32, score: Score) {
+        let doc_id = self.doc_id_reader.get_val(doc);
 
-    for doc_id in doc_ids {
-        let term = Term::from_field_i64(id_field, doc_id);
-        index_writer.delete_term(term);
+        if compare_hits(&(score, doc_id), &self.after) != std::cmp::Ordering::Greater {
+            return;
+        }
+
+        self.hits.push((score, doc_id, DocAddress::new(self.segment_ord, doc)));
+        if self.hits.len() >= self.limit.max(1) * 2 {
+            self.truncate_hits();
+        }
     }
-    
-    if commit {
-        index_writer.commit()?;
-        _ = searcher.index_reader.reload(); // reload reader after commit
+
+    fn harvest(mut self) -> Vec<(Score, i64, DocAddress)> {
+        self.truncate_hits();
+        self.hits
     }
+}
 
-    return Ok(());
+/// SnippetGenerators of SearchParam.snippet.fields for the terms of the query.
+fn snippet_generators(searcher: &Searcher, index_searcher: &tantivy::Searcher, query: &dyn Query, search_param: &SearchParam) -> Result<Vec<(String, SnippetGenerator)>, Box<dyn Error>> {
+    let mut snippet_generators: Vec<(String, SnippetGenerator)> = Vec::new();
+
+    for field_name in &search_param.snippet.fields {
+        let field_option = searcher.schema.get_field(field_name);
+        if field_option.is_err(){
+            bail!(format!("field {field_name} not found! "));
+        }
+        let field = field_option.unwrap();
+
+        let field_entry = searcher.schema.get_field_entry(field);
+        let is_text = match field_entry.field_type() {
+            tantivy::schema::FieldType::Str(text_options) => text_options.get_indexing_options().is_some(),
+            _ => false,
+        };
+        if !is_text || !field_entry.is_stored() {
+            bail!(format!("field {field_name} must be an indexed and stored text field for snippets! "));
+        }
+
+        let mut snippet_generator = SnippetGenerator::create(index_searcher, query, field)?;
+        if search_param.snippet.max_num_chars > 0 {
+            snippet_generator.set_max_num_chars(search_param.snippet.max_num_chars);
+        }
+        snippet_generators.push((field_name.clone(), snippet_generator));
+    }
+
+    return Ok(snippet_generators);
 }
 
-pub fn commit_index(searcher: &mut Searcher)  -> Result<(), Box<dyn Error>> {
-    let index_writer = &mut searcher.index_writer;
+/// Snippets of a hit, the HTML is built here since the tags of Snippet::to_html() are fixed.
+fn snippets(snippet_generators: &Vec<(String, SnippetGenerator)>, retrieved_doc: &Document, snippet_param: &SnippetParam) -> Vec<Snippet> {
+    let mut snippets: Vec<Snippet> = Vec::new();
+
+    for (field_name, snippet_generator) in snippet_generators {
+        let snippet = snippet_generator.snippet_from_doc(retrieved_doc);
+        let fragment = snippet.fragment();
+        let highlights = collapse_highlights(snippet.highlighted());
+
+        let html = match snippet_param.format {
+            SnippetFormat::ranges => String::new(),
+            _ => {
+                let pre_tag = if snippet_param.pre_tag.is_empty() { "<b>" } else { snippet_param.pre_tag.as_str() };
+                let post_tag = if snippet_param.post_tag.is_empty() { "</b>" } else { snippet_param.post_tag.as_str() };
+                highlight_html(fragment, &highlights, pre_tag, post_tag)
+            }
+        };
 
-    index_writer.commit()?;
-    _ = searcher.index_reader.reload(); // reload reader after commit
+        snippets.push(Snippet{field_name: field_name.clone(), fragment: fragment.to_string(), highlights, html});
+    }
 
-    return Ok(());
+    return snippets;
 }
 
-pub fn search(searcher: & mut Searcher, query: & String, search_fields: & Vec<String>, search_param: & SearchParam) -> Result<Vec<IdDocument>, Box<dyn Error>> {
+/// Sorts the highlighted ranges and merges the overlapping ones, e.g. the ngrams "he" 0..2 and "hel" 0..3
+/// into 0..3, so the ranges can be sliced one after the other.
+fn collapse_highlights(ranges: &[std::ops::Range<usize>]) -> Vec<HighlightRange> {
+    let mut sorted: Vec<std::ops::Range<usize>> = ranges.to_vec();
+    sorted.sort_by_key(|range| (range.start, range.end));
+
+    let mut highlights: Vec<HighlightRange> = Vec::new();
+    for range in sorted {
+        match highlights.last_mut() {
+            Some(last) if range.start < last.end => last.end = last.end.max(range.end),
+            _ => highlights.push(HighlightRange{start: range.start, end: range.end}),
+        }
+    }
 
-    let index_searcher = searcher.index_reader.searcher();
-    log::info!("query:{}", query);
+    return highlights;
+}
 
-    let doc_id_field = searcher.schema.get_field("_docId").unwrap();
+/// Escaped fragment with the highlights between pre_tag and post_tag, the highlights must be sorted and disjoint.
+fn highlight_html(fragment: &str, highlights: &Vec<HighlightRange>, pre_tag: &str, post_tag: &str) -> String {
+    let mut html = String::new();
+    let mut start_from = 0;
+    for highlight in highlights {
+        html.push_str(&escape_html(&fragment[start_from..highlight.start]));
+        html.push_str(pre_tag);
+        html.push_str(&escape_html(&fragment[highlight.start..highlight.end]));
+        html.push_str(post_tag);
+        start_from = highlight.end;
+    }
+    html.push_str(&escape_html(&fragment[start_from..]));
 
+    return html;
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    return escaped;
+}
+
+/// Resolves SearchParam.return_fields to schema fields, an empty list selects all the stored fields.
+fn resolve_return_fields(searcher: &Searcher, search_param: &SearchParam) -> Result<Vec<Field>, Box<dyn Error>> {
     let mut fields: Vec<Field> = Vec::new();
 
-    for search_field in search_fields{
-        let field_option = searcher.schema.get_field(search_field.as_str());
+    if !search_param.with_field_values {
+        return Ok(fields);
+    }
 
-        match field_option {
+    if search_param.return_fields.is_empty() {
+        for (field, field_entry) in searcher.schema.fields() {
+            if field_entry.is_stored() && field_entry.name() != "_docId" {
+                fields.push(field);
+            }
+        }
+        return Ok(fields);
+    }
+
+    for return_field in &search_param.return_fields {
+        match searcher.schema.get_field(return_field.as_str()) {
             Ok(field) => {
+                if !searcher.schema.get_field_entry(field).is_stored() {
+                    bail!(format!("field {return_field} is not stored! "));
+                }
                 fields.push(field);
             }
-            Err(err) => {
-                bail!(format!("field {search_field} not found! "));
+            Err(_) => {
+                bail!(format!("field {return_field} not found! "));
             }
         }
     }
 
-    let query_parser = QueryParser::for_index(searcher.index_writer.index(), fields);
-    let query = query_parser.parse_query(query.as_str())?;
+    return Ok(fields);
+}
 
-    let top_docs = index_searcher.search(&query, &TopDocs::with_limit(search_param.topK))?;    
+/// Converts the stored values of the given fields into DocumentFields, a field with several values
+/// yields one DocumentField per value.
+fn stored_field_values(searcher: &Searcher, retrieved_doc: &Document, fields: &Vec<Field>) -> Vec<DocumentField> {
+    let mut field_values: Vec<DocumentField> = Vec::new();
+
+    for field in fields {
+        let field_name = searcher.schema.get_field_name(*field);
+        let field_type = field_type_of(searcher, *field);
+
+        for value in retrieved_doc.get_all(*field) {
+            let field_value = match value {
+                Value::Str(text) => text.clone(),
+                Value::I64(number) => number.to_string(),
+                Value::U64(number) => number.to_string(),
+                Value::F64(number) => number.to_string(),
+                Value::Bool(flag) => flag.to_string(),
+                Value::Date(date_time) => date_time.into_utc().format(&Rfc3339).unwrap_or_default(),
+                Value::Bytes(bytes) => base64::encode(bytes),
+                Value::JsonObject(json_object) => serde_json::to_string(json_object).unwrap_or_default(),
+                _ => {
+                    log::warn!("Not supported value type of field {}", field_name);
+                    continue;
+                }
+            };
+            field_values.push(DocumentField{field_name: field_name.to_string(), field_value, field_type});
+        }
+    }
 
-    let mut id_documents: Vec<IdDocument> =  Vec::new();
+    return field_values;
+}
 
-    for (_score, doc_address) in top_docs {
-        let retrieved_doc = index_searcher.doc(doc_address)?;
-        println!("score:{} {}", _score, searcher.schema.to_json(&retrieved_doc));
+/// FieldType of a schema field, taken from the field mappings and guessed from the schema otherwise.
+fn field_type_of(searcher: &Searcher, field: Field) -> FieldType {
+    let field_entry = searcher.schema.get_field_entry(field);
 
-        let doc_id = retrieved_doc.get_first(doc_id_field) ;
-    
-        if doc_id.is_some() {
-            let current_id = doc_id.expect("error getting doc_id").as_i64().expect("as_i64");
-            let document = IdDocument{docId:current_id, fieldValues: Vec::new(), score: _score };
-            id_documents.push(document);
+    if let Some(field_type) = searcher.field_types.get(field_entry.name()) {
+        return *field_type;
+    }
+
+    match field_entry.field_type() {
+        tantivy::schema::FieldType::I64(_) => FieldType::long_field,
+        tantivy::schema::FieldType::F64(_) => FieldType::double_field,
+        tantivy::schema::FieldType::Bool(_) => FieldType::bool_field,
+        tantivy::schema::FieldType::Date(_) => FieldType::date_field,
+        tantivy::schema::FieldType::U64(_) => FieldType::ulong_field,
+        tantivy::schema::FieldType::Bytes(_) => FieldType::bytes_field,
+        tantivy::schema::FieldType::JsonObject(_) => FieldType::json_field,
+        tantivy::schema::FieldType::Str(text_options) => {
+            match text_options.get_indexing_options() {
+                Some(indexing) if indexing.tokenizer() != "raw" => FieldType::text_field,
+                _ => FieldType::str_field,
+            }
         }
+        _ => FieldType::unspecified_field_type,
     }
+}
 
-    return Ok(id_documents);
+pub fn num_docs(searcher: & mut Searcher) -> Result<u64, Box<dyn Error>> {
+    return Ok(searcher.index_reader.searcher().num_docs());   
 }
 
-pub fn search_by_query(searcher: & mut Searcher, query: & TQuery, search_param: & SearchParam) -> Result<Vec<IdDocument>, Box<dyn Error>> {
+pub fn count_by_query(searcher: & mut Searcher, query: & TQuery) -> Result<u64, Box<dyn Error>> {
     let index_searcher = searcher.index_reader.searcher();
-    //println!("query:{}", query);
+    let count = search_filtered(&index_searcher, query.query.as_ref(), &query.doc_id_filter, Count)?;
 
-    let doc_id_field = searcher.schema.get_field("_docId").unwrap();
-    let top_docs = index_searcher.search(&query.query, &TopDocs::with_limit(search_param.topK))?;    
-
-    let mut id_documents: Vec<IdDocument> =  Vec::new();
+    return Ok(count as u64);
+}
 
-    for (_score, doc_address) in top_docs {
-        let retrieved_doc = index_searcher.doc(doc_address)?;
-        log::info!("score:{} {}", _score, searcher.schema.to_json(&retrieved_doc));
+/// Counts the matches of every query against the same reader snapshot, so the counts are consistent with each other.
+pub fn count_by_queries(searcher: & mut Searcher, queries: & TQueryVec) -> Result<Vec<u64>, Box<dyn Error>> {
+    let index_searcher = searcher.index_reader.searcher();
 
-        let doc_id = retrieved_doc.get_first(doc_id_field) ;
-    
-        if doc_id.is_some() {
-            let current_id = doc_id.expect("error getting doc_id").as_i64().expect("as_i64");
-            let document = IdDocument{docId:current_id, fieldValues: Vec::new(), score: _score };
-            id_documents.push(document);
-        }
+    let mut counts: Vec<u64> = Vec::new();
+    for query in &queries.queries {
+        let count = search_filtered(&index_searcher, query.query.as_ref(), &query.doc_id_filter, Count)?;
+        counts.push(count as u64);
     }
 
-    return Ok(id_documents);
-}
-
-pub fn num_docs(searcher: & mut Searcher) -> Result<u64, Box<dyn Error>> {
-    return Ok(searcher.index_reader.searcher().num_docs());   
+    return Ok(counts);
 }
 
 pub fn search_compact_all(searcher: & mut Searcher, query: & TQuery) -> Result<Box<SearchResultBitmap>, Box<dyn Error>>{
@@ -606,7 +2941,7 @@ pub fn search_compact_all(searcher: & mut Searcher, query: & TQuery) -> Result<B
 
     let doc_id_field = searcher.schema.get_field("_docId").unwrap();
     let collector = DocSetCollector{};
-    let top_docs = index_searcher.search(&query.query, & collector)?;
+    let top_docs = search_filtered(&index_searcher, query.query.as_ref(), &query.doc_id_filter, collector)?;
     log::info!("search_compact_all, search duration:{} ", start.elapsed().as_millis(), );
 
     let mut bitmap = RoaringTreemap::new();
@@ -621,14 +2956,14 @@ pub fn search_compact_all(searcher: & mut Searcher, query: & TQuery) -> Result<B
         match doc_id_reader_option {
             Some(doc_id_reader) => {
                 let doc_id = doc_id_reader.get_val(doc_address.doc_id);
-                bitmap.insert(doc_id.unsigned_abs());
+                bitmap.insert(i64_to_sortable(doc_id));
             }
             None => {
                 let segment_reader = index_searcher.segment_reader(segment_id);
                 let doc_id_reader = segment_reader.fast_fields().i64("_docId").unwrap();
                 let doc_id = doc_id_reader.get_val(doc_address.doc_id);
                 seg_id_readers.insert(segment_id, doc_id_reader);
-                bitmap.insert(doc_id.unsigned_abs());
+                bitmap.insert(i64_to_sortable(doc_id));
             }
         }
         
@@ -639,7 +2974,368 @@ pub fn search_compact_all(searcher: & mut Searcher, query: & TQuery) -> Result<B
     return Ok(Box::new(SearchResultBitmap { bitmap }));
 }
 
-pub fn is_member(result_map: & mut SearchResultBitmap, doc_id: u64) -> Result<bool, Box<dyn Error>> {
-    Ok(result_map.bitmap.contains(doc_id))
+pub fn is_member(result_map: & mut SearchResultBitmap, doc_id: i64) -> Result<bool, Box<dyn Error>> {
+    Ok(result_map.bitmap.contains(i64_to_sortable(doc_id)))
+}
+
+pub fn bitmap_len(result_map: & SearchResultBitmap) -> u64 {
+    result_map.bitmap.len()
+}
+
+pub fn bitmap_min(result_map: & SearchResultBitmap) -> Result<i64, Box<dyn Error>> {
+    match result_map.bitmap.min() {
+        Some(value) => Ok(sortable_to_i64(value)),
+        None => bail!("bitmap is empty"),
+    }
+}
+
+pub fn bitmap_max(result_map: & SearchResultBitmap) -> Result<i64, Box<dyn Error>> {
+    match result_map.bitmap.max() {
+        Some(value) => Ok(sortable_to_i64(value)),
+        None => bail!("bitmap is empty"),
+    }
+}
+
+pub fn bitmap_and(left: & SearchResultBitmap, right: & SearchResultBitmap) -> Box<SearchResultBitmap> {
+    Box::new(SearchResultBitmap { bitmap: &left.bitmap & &right.bitmap })
+}
+
+pub fn bitmap_or(left: & SearchResultBitmap, right: & SearchResultBitmap) -> Box<SearchResultBitmap> {
+    Box::new(SearchResultBitmap { bitmap: &left.bitmap | &right.bitmap })
+}
+
+pub fn bitmap_andnot(left: & SearchResultBitmap, right: & SearchResultBitmap) -> Box<SearchResultBitmap> {
+    Box::new(SearchResultBitmap { bitmap: &left.bitmap - &right.bitmap })
+}
+
+pub fn bitmap_xor(left: & SearchResultBitmap, right: & SearchResultBitmap) -> Box<SearchResultBitmap> {
+    Box::new(SearchResultBitmap { bitmap: &left.bitmap ^ &right.bitmap })
+}
+
+/// Replaces the content of `doc_ids` with at most `max_count` members that are >= `from_doc_id`, in ascending order.
+/// Returns the number of doc ids copied. Each call walks the members lower than `from_doc_id` again,
+/// use bitmap_cursor() to iterate a large bitmap in chunks.
+pub fn bitmap_values(result_map: & SearchResultBitmap, from_doc_id: i64, max_count: usize, doc_ids: & mut Vec<i64>) -> usize {
+    doc_ids.clear();
+
+    let from_value = i64_to_sortable(from_doc_id);
+
+    let from_high = (from_value >> 32) as u32;
+    let from_low = from_value as u32;
+
+    for (high, bitmap) in result_map.bitmap.bitmaps() {
+        if high < from_high {
+            continue;
+        }
+
+        // skip the members of the first bitmap that are lower than from_value
+        let skip = if high == from_high && from_low > 0 { bitmap.rank(from_low - 1) as usize } else { 0 };
+
+        for low in bitmap.iter().skip(skip) {
+            if doc_ids.len() >= max_count {
+                return doc_ids.len();
+            }
+            doc_ids.push(sortable_to_i64(((high as u64) << 32) | low as u64));
+        }
+    }
+
+    return doc_ids.len();
+}
+
+/// Cursor over the members >= `from_doc_id` in ascending order, it iterates a copy of the bitmap
+/// taken now and keeps its position between the bitmap_cursor_next() calls.
+pub fn bitmap_cursor(result_map: & SearchResultBitmap, from_doc_id: i64) -> Box<BitmapCursor> {
+    let mut bitmap = result_map.bitmap.clone();
+    bitmap.remove_range(..i64_to_sortable(from_doc_id));
+
+    return Box::new(BitmapCursor { members: bitmap.into_iter() });
+}
+
+/// Replaces the content of `doc_ids` with the next `max_count` members of the cursor at most,
+/// returns the number of doc ids copied, 0 once the cursor is exhausted.
+pub fn bitmap_cursor_next(cursor: & mut BitmapCursor, max_count: usize, doc_ids: & mut Vec<i64>) -> usize {
+    doc_ids.clear();
+    doc_ids.extend(cursor.members.by_ref().take(max_count).map(sortable_to_i64));
+
+    return doc_ids.len();
+}
+
+/// Serializes the bitmap to the portable roaring format of RoaringTreemap,
+/// the members are the docIds mapped by i64_to_sortable().
+pub fn bitmap_serialize(result_map: & SearchResultBitmap) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut bytes: Vec<u8> = Vec::with_capacity(result_map.bitmap.serialized_size());
+    result_map.bitmap.serialize_into(&mut bytes)?;
+
+    return Ok(bytes);
+}
+
+pub fn bitmap_deserialize(bytes: & [u8]) -> Result<Box<SearchResultBitmap>, Box<dyn Error>> {
+    let bitmap = RoaringTreemap::deserialize_from(bytes)?;
+
+    return Ok(Box::new(SearchResultBitmap { bitmap }));
+}
+
+/// Serializes the bitmap as a RoaringTreemap of the plain docIds, for consumers outside this lib.
+/// Negative docIds can not be represented and fail the serialization.
+pub fn bitmap_serialize_doc_ids(result_map: & SearchResultBitmap) -> Result<Vec<u8>, Box<dyn Error>> {
+    if let Some(min) = result_map.bitmap.min() {
+        if sortable_to_i64(min) < 0 {
+            bail!(format!("negative docId {} can not be serialized as plain docId", sortable_to_i64(min)));
+        }
+    }
+
+    // the members are sorted and the docIds non negative, so the docIds are sorted too
+    let plain = RoaringTreemap::from_sorted_iter(result_map.bitmap.iter().map(|value| sortable_to_i64(value) as u64)).map_err(|err| format!("{err:?}"))?;
+
+    let mut bytes: Vec<u8> = Vec::with_capacity(plain.serialized_size());
+    plain.serialize_into(&mut bytes)?;
+
+    return Ok(bytes);
+}
+
+/// Deserializes a RoaringTreemap of plain non negative docIds, e.g. a permission set built by another service.
+pub fn bitmap_deserialize_doc_ids(bytes: & [u8]) -> Result<Box<SearchResultBitmap>, Box<dyn Error>> {
+    let plain = RoaringTreemap::deserialize_from(bytes)?;
+
+    if let Some(max) = plain.max() {
+        if max > i64::MAX as u64 {
+            bail!(format!("docId {max} is greater than i64::MAX"));
+        }
+    }
+
+    let bitmap = RoaringTreemap::from_sorted_iter(plain.iter().map(|doc_id| i64_to_sortable(doc_id as i64))).map_err(|err| format!("{err:?}"))?;
+
+    return Ok(Box::new(SearchResultBitmap { bitmap }));
+}
+
+pub fn bitmap_from_doc_ids(doc_ids: & Vec<i64>) -> Box<SearchResultBitmap> {
+    let bitmap: RoaringTreemap = doc_ids.iter().map(|doc_id| i64_to_sortable(*doc_id)).collect();
+
+    return Box::new(SearchResultBitmap { bitmap });
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tv_synonyms() -> HashMap<String, Vec<(String, f32)>> {
+        build_synonyms(&vec![SynonymGroup{
+            terms: vec!["tv".to_string(), "television".to_string(), "flat screen".to_string()],
+            boosts: vec![1.0, 0.8, 0.5],
+        }]).unwrap()
+    }
+
+    #[test]
+    fn expand_query_synonyms_expands_plain_words() {
+        let synonyms = tv_synonyms();
+
+        assert_eq!(expand_query_synonyms(&synonyms, "cheap TV"), "cheap (TV OR \"television\"^0.8 OR \"flat screen\"^0.5)");
+        assert_eq!(expand_query_synonyms(&synonyms, "television"), "(television OR \"tv\"^1 OR \"flat screen\"^0.5)");
+        assert_eq!(expand_query_synonyms(&synonyms, "radio"), "radio");
+    }
+
+    #[test]
+    fn expand_query_synonyms_keeps_field_prefix() {
+        let synonyms = tv_synonyms();
+
+        assert_eq!(
+            expand_query_synonyms(&synonyms, "title:tv AND price:10"),
+            "(title:tv OR title:\"television\"^0.8 OR title:\"flat screen\"^0.5) AND price:10"
+        );
+    }
+
+    #[test]
+    fn expand_query_synonyms_keeps_phrases_groups_and_modifiers() {
+        let synonyms = tv_synonyms();
+
+        for query in ["\"smart tv box\"", "title:\"smart tv\"", "(tv OR radio)", "title:(tv radio)", "+tv", "-tv", "tv^2"] {
+            assert_eq!(expand_query_synonyms(&synonyms, query), query);
+        }
+
+        assert_eq!(
+            expand_query_synonyms(&synonyms, "\"old tv\" tv"),
+            "\"old tv\" (tv OR \"television\"^0.8 OR \"flat screen\"^0.5)"
+        );
+    }
+
+    #[test]
+    fn read_synonym_file_parses_boosts_and_comments() {
+        let path = std::env::temp_dir().join(format!("synonyms_{}.txt", std::process::id()));
+        std::fs::write(&path, "# media\n\ntv, television^0.8 , telly^0.5\n  # indented comment\ncouch,sofa\n").unwrap();
+
+        let synonym_groups = read_synonym_file(&path.to_string_lossy().to_string()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(synonym_groups.len(), 2);
+        assert_eq!(synonym_groups[0].terms, vec!["tv", "television", "telly"]);
+        assert_eq!(synonym_groups[0].boosts, vec![1.0, 0.8, 0.5]);
+        assert_eq!(synonym_groups[1].terms, vec!["couch", "sofa"]);
+        assert_eq!(synonym_groups[1].boosts, vec![1.0, 1.0]);
+    }
+
+    #[test]
+    fn synonym_query_analyzes_like_the_field() {
+        let field = Field::from_field_id(1);
+        let analyzer = TextAnalyzer::from(SimpleTokenizer).filter(LowerCaser);
+
+        let single = analyze_terms(&analyzer, field, "Television");
+        assert_eq!(single, vec![(0, Term::from_field_text(field, "television"))]);
+        assert!(format!("{:?}", synonym_query(single, true).unwrap()).contains("TermQuery"));
+
+        let several = analyze_terms(&analyzer, field, "Flat Screen");
+        assert_eq!(several, vec![(0, Term::from_field_text(field, "flat")), (1, Term::from_field_text(field, "screen"))]);
+        assert!(format!("{:?}", synonym_query(several.clone(), true).unwrap()).contains("PhraseQuery"));
+        assert!(format!("{:?}", synonym_query(several, false).unwrap()).contains("BooleanQuery"));
+
+        assert!(synonym_query(analyze_terms(&analyzer, field, " , "), true).is_none());
+    }
+
+    #[test]
+    fn snippet_html_collapses_overlapping_ngrams() {
+        let analyzer = build_analyzer(&AnalyzerConfig{
+            name: "ngram".to_string(),
+            tokenizer: BaseTokenizer::ngram_tokenizer,
+            ngram_min: 2,
+            ngram_max: 3,
+            ngram_prefix_only: false,
+            remove_long: 0,
+            lowercase: true,
+            ascii_folding: false,
+            stop_words: Vec::new(),
+            stemmer_language: String::new(),
+        }).unwrap();
+
+        // highlight every ngram of "hel", as the SnippetGenerator would for a query on "hel"
+        let fragment = "hello <world>";
+        let mut ranges: Vec<std::ops::Range<usize>> = Vec::new();
+        analyzer.token_stream(fragment).process(&mut |token: &Token| {
+            if "hel".contains(token.text.as_str()) && token.offset_to <= 3 {
+                ranges.push(token.offset_from..token.offset_to);
+            }
+        });
+        assert!(ranges.len() > 1);
+
+        let highlights = collapse_highlights(&ranges);
+        assert_eq!(highlights.iter().map(|highlight| (highlight.start, highlight.end)).collect::<Vec<_>>(), vec![(0, 3)]);
+        assert_eq!(highlight_html(fragment, &highlights, "<em>", "</em>"), "<em>hel</em>lo &lt;world&gt;");
+    }
+
+    #[test]
+    fn collapse_highlights_sorts_and_merges() {
+        let highlights = collapse_highlights(&[6..9, 0..2, 1..3, 3..4, 7..8]);
+
+        assert_eq!(highlights.iter().map(|highlight| (highlight.start, highlight.end)).collect::<Vec<_>>(), vec![(0, 3), (3, 4), (6, 9)]);
+    }
+
+    #[test]
+    fn build_synonyms_rejects_mismatched_boosts() {
+        let synonym_groups = vec![SynonymGroup{ terms: vec!["tv".to_string(), "television".to_string()], boosts: vec![0.5] }];
+
+        assert!(build_synonyms(&synonym_groups).is_err());
+    }
+
+    #[test]
+    fn bitmap_cursor_iterates_in_chunks() {
+        let doc_ids: Vec<i64> = (-3..10).collect();
+        let result_map = bitmap_from_doc_ids(&doc_ids);
+
+        let mut cursor = bitmap_cursor(&result_map, -1);
+        let mut chunk: Vec<i64> = Vec::new();
+        let mut all: Vec<i64> = Vec::new();
+        while bitmap_cursor_next(&mut cursor, 4, &mut chunk) > 0 {
+            assert!(chunk.len() <= 4);
+            all.extend(&chunk);
+        }
+
+        assert_eq!(all, (-1..10).collect::<Vec<i64>>());
+        assert_eq!(bitmap_cursor_next(&mut cursor, 4, &mut chunk), 0);
+    }
+
+    #[test]
+    fn i64_to_sortable_preserves_order() {
+        let values = [i64::MIN, i64::MIN + 1, -5, -1, 0, 1, 5, i64::MAX - 1, i64::MAX];
+
+        for pair in values.windows(2) {
+            assert!(i64_to_sortable(pair[0]) < i64_to_sortable(pair[1]));
+        }
+        for value in values {
+            assert_eq!(sortable_to_i64(i64_to_sortable(value)), value);
+        }
+        assert_eq!(i64_to_sortable(i64::MIN), 0);
+        assert_eq!(i64_to_sortable(i64::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn f64_to_sortable_preserves_order() {
+        let values = [f64::NEG_INFINITY, f64::MIN, -1.5, -f64::MIN_POSITIVE, -0.0, 0.0, f64::MIN_POSITIVE, 1.5, f64::MAX, f64::INFINITY];
+
+        for pair in values.windows(2) {
+            assert!(f64_to_sortable(pair[0]) < f64_to_sortable(pair[1]), "{} < {}", pair[0], pair[1]);
+        }
+        for value in values {
+            assert_eq!(sortable_to_f64(f64_to_sortable(value)).to_bits(), value.to_bits());
+        }
+
+        // NaNs rank after +infinity, or before -infinity with the sign bit set
+        assert!(f64_to_sortable(f64::NAN) > f64_to_sortable(f64::INFINITY));
+        assert!(f64_to_sortable(-f64::NAN) < f64_to_sortable(f64::NEG_INFINITY));
+        assert!(sortable_to_f64(f64_to_sortable(f64::NAN)).is_nan());
+    }
+
+    #[test]
+    fn bitmap_keeps_negative_doc_ids() {
+        let result_map = bitmap_from_doc_ids(&vec![i64::MAX, -5, 5, i64::MIN, 0]);
+
+        assert_eq!(bitmap_len(&result_map), 5);
+        assert_eq!(bitmap_min(&result_map).unwrap(), i64::MIN);
+        assert_eq!(bitmap_max(&result_map).unwrap(), i64::MAX);
+
+        let mut doc_ids: Vec<i64> = Vec::new();
+        bitmap_values(&result_map, i64::MIN, 10, &mut doc_ids);
+        assert_eq!(doc_ids, vec![i64::MIN, -5, 0, 5, i64::MAX]);
+    }
+
+    #[test]
+    fn bitmap_plain_doc_ids_round_trip() {
+        let mut plain = RoaringTreemap::new();
+        plain.insert(1);
+        plain.insert(2);
+        plain.insert(i64::MAX as u64);
+        let mut bytes: Vec<u8> = Vec::new();
+        plain.serialize_into(&mut bytes).unwrap();
+
+        let result_map = bitmap_deserialize_doc_ids(&bytes).unwrap();
+        let mut doc_ids: Vec<i64> = Vec::new();
+        bitmap_values(&result_map, i64::MIN, 10, &mut doc_ids);
+        assert_eq!(doc_ids, vec![1, 2, i64::MAX]);
+
+        let serialized = bitmap_serialize_doc_ids(&result_map).unwrap();
+        assert_eq!(RoaringTreemap::deserialize_from(&serialized[..]).unwrap(), plain);
+
+        // negative docIds have no plain representation
+        assert!(bitmap_serialize_doc_ids(&bitmap_from_doc_ids(&vec![-1, 1])).is_err());
+
+        // ids above i64::MAX are not docIds
+        let mut too_large = RoaringTreemap::new();
+        too_large.insert(u64::MAX);
+        let mut too_large_bytes: Vec<u8> = Vec::new();
+        too_large.serialize_into(&mut too_large_bytes).unwrap();
+        assert!(bitmap_deserialize_doc_ids(&too_large_bytes).is_err());
+    }
+
+    #[test]
+    fn bitmap_values_matches_cursor() {
+        let doc_ids: Vec<i64> = vec![i64::MIN, -7, 0, 5, 1 << 40, i64::MAX];
+        let result_map = bitmap_from_doc_ids(&doc_ids);
+
+        let mut values: Vec<i64> = Vec::new();
+        bitmap_values(&result_map, 0, 10, &mut values);
+
+        let mut cursor = bitmap_cursor(&result_map, 0);
+        let mut chunk: Vec<i64> = Vec::new();
+        bitmap_cursor_next(&mut cursor, 10, &mut chunk);
+
+        assert_eq!(values, vec![0, 5, 1 << 40, i64::MAX]);
+        assert_eq!(chunk, values);
+    }
+}